@@ -0,0 +1,56 @@
+use prover::eth_types::l2_types::BlockTrace;
+use serde_derive::{Deserialize, Serialize};
+
+#[cfg(feature = "batch-prove")]
+use prover::{eth_types::H256, BatchHeader, ChunkInfo, MAX_AGG_SNARKS};
+
+/// Batch header fields which come from block traces, accumulated chunk by chunk.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct BatchMeta {
+    /// Number of L1 messages popped before this batch, i.e. the queue index of the first block.
+    pub prev_total_l1_message_popped: Option<u64>,
+    /// Number of L1 messages popped in this batch, including skipped ones.
+    pub l1_message_popped: u64,
+    pub last_block_timestamp: u64,
+}
+
+impl BatchMeta {
+    pub fn add_chunk(&mut self, block_traces: &[BlockTrace]) {
+        if let Some(first_block) = block_traces.first() {
+            self.prev_total_l1_message_popped
+                .get_or_insert(first_block.start_l1_queue_index);
+        }
+        self.l1_message_popped += block_traces.iter().map(|b| b.num_l1_txs()).sum::<u64>();
+        if let Some(last_block) = block_traces.last() {
+            self.last_block_timestamp = last_block.header.timestamp.as_u64();
+        }
+    }
+
+    /// Queue index of the next L1 message, if a chunk has been added.
+    pub fn next_l1_queue_index(&self) -> Option<u64> {
+        self.prev_total_l1_message_popped
+            .map(|popped| popped + self.l1_message_popped)
+    }
+
+    /// `chunks` are unpadded.
+    #[cfg(feature = "batch-prove")]
+    pub fn build_header(
+        &self,
+        version: u8,
+        batch_index: u64,
+        parent_batch_hash: H256,
+        chunks: &[ChunkInfo],
+        blob_bytes: &[u8],
+    ) -> BatchHeader<MAX_AGG_SNARKS> {
+        BatchHeader::construct_from_chunks(
+            version,
+            batch_index,
+            self.l1_message_popped,
+            self.prev_total_l1_message_popped.unwrap_or_default() + self.l1_message_popped,
+            parent_batch_hash,
+            self.last_block_timestamp,
+            chunks,
+            blob_bytes,
+        )
+    }
+}
@@ -0,0 +1,158 @@
+use crate::{
+    prove_utils::panic_message,
+    run_report::{self, BundleRecord, Outcome, ReportRecord},
+};
+use halo2_proofs::{halo2curves::bn256::Bn256, poly::kzg::commitment::ParamsKZG};
+use integration::prove::{new_batch_prover, prove_and_verify_batch, prove_and_verify_bundle};
+use itertools::Itertools;
+use prover::{
+    eth_types::H256, BatchProofV2, BatchProver, BatchProvingTask, BundleProvingTask,
+    BATCH_PROVER_DEGREES,
+};
+use std::{
+    collections::BTreeMap,
+    fs,
+    panic::{catch_unwind, AssertUnwindSafe},
+    time::Instant,
+};
+
+/// Proves batches, and groups the proofs of consecutive batches into bundles.
+///
+/// With a `bundle_size` of 0 bundles follow the chain, i.e. a bundle ends where the bundle hash
+/// reported by the chain changes. Otherwise every `bundle_size` batches make a bundle.
+/// A batch which is skipped or fails to prove ends the pending bundle early, since the batches of
+/// a bundle must be consecutive.
+pub struct Bundler {
+    params_map: &'static BTreeMap<u32, ParamsKZG<Bn256>>,
+    output_dir: String,
+    bundle_size: usize,
+    // Constructed with the chunk protocol of the first proven batch.
+    prover: Option<BatchProver<'static>>,
+    // Pending bundle.
+    batch_proofs: Vec<BatchProofV2>,
+    first_batch: i64,
+    last_batch: i64,
+    bundle_hash: Option<H256>,
+}
+
+impl Bundler {
+    pub fn new(params_dir: &str, output_dir: &str, bundle_size: usize) -> Self {
+        fs::create_dir_all(output_dir)
+            .unwrap_or_else(|e| panic!("bundler: failed to create dir {output_dir}: {e}"));
+        let params_map = prover::Prover::load_params_map(
+            params_dir,
+            &BATCH_PROVER_DEGREES.iter().copied().collect_vec(),
+        );
+
+        Self {
+            // The batch prover borrows the params for the whole run.
+            params_map: Box::leak(Box::new(params_map)),
+            output_dir: output_dir.to_string(),
+            bundle_size,
+            prover: None,
+            batch_proofs: vec![],
+            first_batch: 0,
+            last_batch: 0,
+            bundle_hash: None,
+        }
+    }
+
+    /// Adds the batch proof to the pending bundle. The bundle is proven once complete.
+    /// `batch_proof` is `None` if the batch couldn't be proven, `bundle_hash` if not reported by
+    /// the chain.
+    pub fn add_batch(
+        &mut self,
+        batch_id: i64,
+        batch_proof: Option<BatchProofV2>,
+        bundle_hash: Option<H256>,
+    ) {
+        let same_bundle = batch_id == self.last_batch + 1
+            && (self.bundle_size > 0 || bundle_hash == self.bundle_hash);
+        if !same_bundle {
+            self.finish();
+        }
+
+        let Some(batch_proof) = batch_proof else {
+            self.finish();
+            return;
+        };
+        if self.batch_proofs.is_empty() {
+            self.first_batch = batch_id;
+        }
+        self.last_batch = batch_id;
+        self.bundle_hash = bundle_hash;
+        self.batch_proofs.push(batch_proof);
+
+        if self.bundle_size > 0 && self.batch_proofs.len() >= self.bundle_size {
+            self.finish();
+        }
+    }
+
+    /// Proves the pending bundle, if any.
+    pub fn finish(&mut self) {
+        if self.batch_proofs.is_empty() {
+            return;
+        }
+        let id = format!(
+            "chain_prover: bundle of batch-{}..=batch-{}",
+            self.first_batch, self.last_batch
+        );
+        let bundle = BundleProvingTask {
+            batch_proofs: std::mem::take(&mut self.batch_proofs),
+        };
+        let num_batches = bundle.batch_proofs.len();
+        log::info!("{id}: proving {num_batches} batches");
+
+        // A batch proof only exists once the prover is constructed.
+        let prover = self.prover.as_mut().unwrap();
+        let start = Instant::now();
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            prove_and_verify_bundle(&self.output_dir, prover, bundle)
+        }))
+        .map_err(panic_message);
+
+        match &result {
+            Ok(_) => log::info!("{id}: succeeded to prove bundle"),
+            Err(panic_err) => log::error!("{id}: failed to prove bundle:\n{panic_err:?}"),
+        }
+        run_report::record(&ReportRecord::Bundle(BundleRecord {
+            first_batch: self.first_batch,
+            last_batch: self.last_batch,
+            num_batches,
+            outcome: Outcome::new(&result, start.elapsed()),
+        }));
+    }
+
+    pub fn prove_batch(
+        &mut self,
+        batch_id: i64,
+        batch: BatchProvingTask,
+    ) -> Result<BatchProofV2, String> {
+        let id = format!("chain_prover: batch-{batch_id}");
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            let prover = self.prover.get_or_insert_with(|| {
+                // Dump chunk-procotol to "protocol_chunk_0.protocol" for batch proving.
+                batch
+                    .chunk_proofs
+                    .first()
+                    .unwrap()
+                    .dump(&self.output_dir, "0")
+                    .unwrap();
+                new_batch_prover(self.params_map, &self.output_dir)
+            });
+            prove_and_verify_batch(self.params_map, &self.output_dir, prover, batch)
+        }));
+
+        match result {
+            Ok(batch_proof) => {
+                log::info!("{id}: succeeded to prove batch");
+                Ok(batch_proof)
+            }
+            Err(err) => {
+                let panic_err = panic_message(err);
+                log::error!("{id}: failed to prove batch:\n{panic_err:?}");
+                Err(panic_err)
+            }
+        }
+    }
+}
@@ -3,181 +3,145 @@
 // Instead this is more as a testing tool.
 // For production prover, see https://github.com/scroll-tech/scroll/tree/develop/prover
 
+use batch_meta::BatchMeta;
+use chain_source::ChainSource;
+use checkpoint::{BatchProveCheckpoint, BlockProveCheckpoint, Checkpointer};
+use failure_ledger::{FailedItem, FailureLedger, FailurePolicy};
 use integration::{
-    capacity_checker::{
-        ccc_by_chunk, prepare_circuit_capacity_checker, run_circuit_capacity_checker, CCCMode,
-    },
+    capacity_checker::{ccc_by_chunk, prepare_circuit_capacity_checker},
+    constants,
+    l1_queue::check_l1_queue,
     l2geth,
+    proposer::{
+        blob_size, chunk_info_from_traces, BatchBuilder, BatchLimits, ChunkBuilder, ChunkLimits,
+        ProposedBatch, ProposedChunk,
+    },
+    retry::RetryConfig,
+    root_check::{check_roots, Roots},
+    trace_cache::{CacheMode, TraceCache},
+    trace_check::check_traces,
+    trace_options::TraceOptions,
 };
+use itertools::Itertools;
+use prefetch::{PrefetchConfig, TracePrefetcher};
+use proposer_sim::SweepSpec;
 use prover::{
-    eth_types::l2_types::BlockTrace, init_env_and_log, BatchData, ChunkInfo, ChunkProofV2,
-    CircuitCapacityChecker, RowUsage, MAX_AGG_SNARKS,
+    eth_types::l2_types::BlockTrace, init_env_and_log, ChunkProofV2, CircuitCapacityChecker,
+};
+use rand::SeedableRng;
+use rand_xorshift::XorShiftRng;
+use run_report::{BatchRecord, ChunkRecord, Outcome, ReportRecord};
+use std::{
+    collections::VecDeque,
+    env,
+    sync::Arc,
+    time::{Duration, Instant},
 };
-use std::env;
+use tokio::task::JoinHandle;
 
-mod constants;
+use prover::eth_types::H256;
+#[cfg(feature = "batch-prove")]
+use prover::BatchProvingTask;
+#[cfg(feature = "batch-prove")]
+use rollupscan_client::BatchInfo;
+
+mod batch_meta;
+#[cfg(feature = "bundle-prove")]
+mod bundler;
+mod chain_source;
+mod checkpoint;
+mod chunking_diff;
+mod failure_ledger;
+mod prefetch;
+mod proposer_sim;
 mod prove_utils;
+mod replay_client;
 mod rollupscan_client;
+mod root_checker;
+mod run_report;
 
 fn warmup() {
     prepare_circuit_capacity_checker();
     log::info!("chain_prover: prepared ccc");
 }
 
-struct BatchBuilder {
-    chunks: Vec<ChunkInfo>,
-    batch_data: BatchData<{ MAX_AGG_SNARKS }>,
-}
-
-impl BatchBuilder {
-    pub fn new() -> Self {
-        Self {
-            chunks: Vec::new(),
-            batch_data: BatchData {
-                num_valid_chunks: 0,
-                chunk_sizes: [0u32; MAX_AGG_SNARKS],
-                chunk_data: std::iter::repeat_with(Vec::new)
-                    .take(MAX_AGG_SNARKS)
-                    .collect::<Vec<_>>()
-                    .try_into()
-                    .unwrap(),
-            },
-        }
-    }
-    fn reset(&mut self) {
-        self.chunks.clear();
-        self.batch_data = BatchData {
-            num_valid_chunks: 0,
-            chunk_sizes: [0u32; MAX_AGG_SNARKS],
-            chunk_data: std::iter::repeat_with(Vec::new)
-                .take(MAX_AGG_SNARKS)
-                .collect::<Vec<_>>()
-                .try_into()
-                .unwrap(),
-        };
-    }
-    fn add_chunk(&mut self, chunk: ChunkInfo) {
-        let idx = self.batch_data.num_valid_chunks as usize;
-        self.batch_data.chunk_sizes[idx] = chunk.tx_bytes.len() as u32;
-        self.batch_data.chunk_data[idx] = chunk.tx_bytes.clone();
-        self.batch_data.num_valid_chunks += 1;
-        self.chunks.push(chunk);
-    }
-    pub fn add(&mut self, chunk: ChunkInfo) -> Option<Vec<ChunkInfo>> {
-        self.add_chunk(chunk.clone());
-        log::debug!(
-            "BatchBuilder: checking chunk with len {}",
-            self.chunks.len()
-        );
-
-        // Condition0: chunk num
-        let condition0 = self.chunks.len() >= MAX_AGG_SNARKS;
-        if condition0 {
-            let batch = self.chunks.clone();
-            self.reset();
-            return Some(batch);
-        }
-
-        let batch_bytes = self.batch_data.get_batch_data_bytes();
-        let blob_bytes = prover::get_blob_bytes(&batch_bytes);
-        let compressed_da_size = blob_bytes.len();
-        let uncompressed_da_size = self
-            .batch_data
-            .chunk_sizes
-            .iter()
-            .map(|s| *s as u64)
-            .sum::<u64>();
-        let uncompressed_da_size_limit = BatchData::<{ MAX_AGG_SNARKS }>::n_rows_data() as u64;
-        // Condition1: compressed bytes size
-        let condition1 = compressed_da_size >= constants::N_BLOB_BYTES;
-        // Condition2: uncompressed bytes size
-        let condition2 = uncompressed_da_size > uncompressed_da_size_limit;
-
-        let overflow = condition1 || condition2;
-        if overflow {
-            // pop the last chunk and emit prev chunks
-            self.chunks.truncate(self.chunks.len() - 1);
-            let batch = self.chunks.clone();
-
-            self.reset();
-            self.add_chunk(chunk);
-
-            Some(batch)
+// Construct chunk myself
+async fn prove_by_block(
+    source: &Arc<ChainSource>,
+    begin_block: i64,
+    end_block: i64,
+    checkpointer: &Checkpointer,
+    prefetch_config: PrefetchConfig,
+    ledger: &FailureLedger,
+    chunk_limits: &ChunkLimits,
+    batch_limits: &BatchLimits,
+) {
+    let mut chunk_builder = ChunkBuilder::new(chunk_limits.clone());
+    let mut batch_builder = BatchBuilder::new(batch_limits.clone());
+    let (begin_block, end_block, next_block, mut batch_begin_block) =
+        if let Some(checkpoint) = checkpointer.load::<BlockProveCheckpoint>() {
+            chunk_builder.restore(
+                checkpoint.pending_traces,
+                checkpoint.acc_row_usage_normalized,
+            );
+            batch_builder.restore(checkpoint.pending_chunks);
+            (
+                checkpoint.begin_block,
+                checkpoint.end_block,
+                checkpoint.next_block,
+                checkpoint.batch_begin_block,
+            )
         } else {
-            None
-        }
-    }
-}
-
-struct ChunkBuilder {
-    traces: Vec<BlockTrace>,
-    acc_row_usage_normalized: RowUsage,
-    block_limit: Option<usize>,
-}
-
-/// Same with production "chunk proposer"
-impl ChunkBuilder {
-    pub fn new() -> Self {
-        Self {
-            traces: Vec::new(),
-            acc_row_usage_normalized: RowUsage::default(),
-            block_limit: None,
-        }
-    }
-    pub fn add(&mut self, trace: BlockTrace) -> Option<Vec<BlockTrace>> {
-        // Condition1: block num
-        if let Some(block_limit) = self.block_limit {
-            if self.traces.len() + 1 == block_limit {
-                // build chunk
-                let mut chunk = self.traces.clone();
-                chunk.push(trace.clone());
-                self.traces.clear();
-                return Some(chunk);
-            }
-        }
-
-        // Condition2: ccc
-        let ccc_result = {
-            let mut checker = CircuitCapacityChecker::new();
-            checker.estimate_circuit_capacity(trace.clone()).unwrap()
+            let (begin_block, end_block) = if begin_block == 0 && end_block == 0 {
+                source.get_default_block_range().await.unwrap()
+            } else {
+                (begin_block, end_block)
+            };
+            (begin_block, end_block, begin_block, begin_block)
         };
-        self.acc_row_usage_normalized.add(&ccc_result);
-        if !self.acc_row_usage_normalized.is_ok {
-            // build a chunk with PREV traces
-            let chunk = self.traces.clone();
-            self.traces.clear();
-            self.traces.push(trace);
-            self.acc_row_usage_normalized = ccc_result;
-            Some(chunk)
-        } else {
-            self.traces.push(trace);
-            None
-        }
-    }
-}
-
-// Construct chunk myself
-async fn prove_by_block(l2geth: &l2geth::Client, begin_block: i64, end_block: i64) {
-    let mut chunk_builder = ChunkBuilder::new();
-    //chunk_builder.block_limit = Some(1);
-    let mut batch_builder = BatchBuilder::new();
-    let (begin_block, end_block) = if begin_block == 0 && end_block == 0 {
-        // Blocks within last 24 hours
-        let block_num = 24 * 1200;
-        log::info!("use latest {block_num} blocks");
-        let latest_block = l2geth.get_block_number().await.unwrap();
-        (latest_block as i64 - block_num, latest_block as i64)
-    } else {
-        (begin_block, end_block)
+    let mut prefetcher = TracePrefetcher::new(source.clone(), prefetch_config);
+    prefetcher.push_blocks(next_block..=end_block);
+    let mut gate = ContinuityGate::default();
+    // Dropped blocks are in the ledger, so a resume goes on after them.
+    let save_checkpoint = |next_block: i64,
+                           batch_begin_block: i64,
+                           chunk_builder: &ChunkBuilder,
+                           batch_builder: &BatchBuilder| {
+        checkpointer.save(&BlockProveCheckpoint {
+            begin_block,
+            end_block,
+            next_block,
+            batch_begin_block,
+            pending_traces: chunk_builder.pending().to_vec(),
+            acc_row_usage_normalized: chunk_builder.row_usage().clone(),
+            pending_chunks: batch_builder.pending().to_vec(),
+        })
     };
-    let mut batch_begin_block = begin_block;
-    for block_num in begin_block..=end_block {
-        let trace = l2geth
-        .get_block_trace_by_num(block_num, false)
-        .await
-        .unwrap_or_else(|e| {
-            panic!("chain_prover: failed to request l2geth block-trace API for block-{block_num}: {e}")
-        });
+    while let Some((block_num, trace)) = prefetcher.next().await {
+        let trace = match trace {
+            Ok(trace) => trace,
+            Err(e) => {
+                // The pending chunk can't go on past the missing block, so drop it with the block
+                // and start the next chunk after them.
+                let chunk_begin_block = chunk_builder
+                    .finish()
+                    .map_or(block_num, |chunk| block_num_of(&chunk.traces[0]));
+                gate.reset();
+                save_checkpoint(
+                    block_num + 1,
+                    batch_begin_block,
+                    &chunk_builder,
+                    &batch_builder,
+                );
+                ledger.fail(
+                    FailedItem::blocks(chunk_begin_block, block_num),
+                    e.context(format!(
+                        "chain_prover: failed to request l2geth block-trace API for block-{block_num}"
+                    )),
+                );
+                continue;
+            }
+        };
         log::info!(
             "fetch trace done. begin {} end {} cur {}, progress {:.1}%",
             begin_block,
@@ -186,52 +150,180 @@ async fn prove_by_block(l2geth: &l2geth::Client, begin_block: i64, end_block: i6
             100.0 * (block_num - begin_block + 1) as f32 / (end_block - begin_block + 1) as f32
         );
         if let Some(chunk) = chunk_builder.add(trace) {
-            prove_chunk(0, chunk[0].header.number.unwrap().as_u64(), chunk.clone());
-            let fast = false;
-            let chunk_info = if fast {
-                unimplemented!("uncomment below");
-                //ChunkInfo::from_block_traces(&chunk)
-            } else {
-                let witness_block = prover::chunk_trace_to_witness_block(chunk).unwrap();
-                ChunkInfo::from_witness_block(&witness_block, false)
-            };
-            if let Some(batch) = batch_builder.add(chunk_info) {
-                let mut padded_batch = batch.clone();
-                padding_chunk(&mut padded_batch);
-                let batch_data = BatchData::<{ MAX_AGG_SNARKS }>::new(batch.len(), &padded_batch);
-                let compressed_da_size =
-                    prover::get_blob_bytes(&batch_data.get_batch_data_bytes()).len();
-                log::info!(
-                    "batch built: blob usage {:.3}, chunk num {}, block num {}, block range {} to {}",
-                    compressed_da_size as f32 / constants::N_BLOB_BYTES as f32,
-                    batch.len(),
-                    block_num - batch_begin_block + 1,
+            if let Err(e) = gate.check(&chunk.traces, None) {
+                let chunk_begin_block = block_num_of(&chunk.traces[0]);
+                let chunk_end_block = block_num_of(chunk.traces.last().unwrap());
+                save_checkpoint(
+                    block_num + 1,
                     batch_begin_block,
-                    block_num,
+                    &chunk_builder,
+                    &batch_builder,
+                );
+                ledger.fail(
+                    FailedItem::blocks(chunk_begin_block, chunk_end_block),
+                    anyhow::anyhow!(
+                        "chain_prover: chunk of block-{chunk_begin_block} to block-{chunk_end_block} {e}"
+                    ),
                 );
-                batch_begin_block = block_num + 1;
+                continue;
             }
+            build_chunk(chunk, &mut batch_builder, &mut batch_begin_block, block_num);
+            save_checkpoint(
+                block_num + 1,
+                batch_begin_block,
+                &chunk_builder,
+                &batch_builder,
+            );
         }
     }
+    prefetcher.log_stats("chain_prover: block_prove");
+    checkpointer.clear();
 }
 
-fn padding_chunk(chunks: &mut Vec<ChunkInfo>) {
-    assert_ne!(chunks.len(), 0);
-    assert!(chunks.len() <= MAX_AGG_SNARKS);
-    if chunks.len() < MAX_AGG_SNARKS {
-        log::warn!(
-            "chunk len({}) < MAX_AGG_SNARKS({}), padding...",
-            chunks.len(),
-            MAX_AGG_SNARKS
+fn block_num_of(trace: &BlockTrace) -> i64 {
+    trace.header.number.unwrap().as_u64() as i64
+}
+
+// Prove the chunk ending at `block_num` and add it to the batch being built.
+fn build_chunk(
+    chunk: ProposedChunk,
+    batch_builder: &mut BatchBuilder,
+    batch_begin_block: &mut i64,
+    block_num: i64,
+) {
+    let ProposedChunk {
+        traces: chunk, cut, ..
+    } = chunk;
+    log::info!(
+        "chunk built: block num {}, block range {} to {}, cut by {cut:?}",
+        chunk.len(),
+        chunk[0].header.number.unwrap(),
+        chunk.last().unwrap().header.number.unwrap(),
+    );
+    let chunk_info = chunk_info_from_traces(&chunk);
+    prove_chunk(0, chunk[0].header.number.unwrap().as_u64(), chunk);
+    if let Some(ProposedBatch { chunks: batch, cut }) = batch_builder.add(chunk_info) {
+        // A single chunk the codec can't encode makes a batch by itself.
+        let blob_utilization = blob_size(batch_builder.limits().codec, &batch)
+            .ok()
+            .map(|compressed_da_size| compressed_da_size as f64 / constants::N_BLOB_BYTES as f64);
+        log::info!(
+            "batch built: blob usage {}, chunk num {}, block num {}, block range {} to {}, cut by {cut:?}",
+            blob_utilization.map_or("n/a".to_string(), |usage| format!("{usage:.3}")),
+            batch.len(),
+            block_num - *batch_begin_block + 1,
+            batch_begin_block,
+            block_num,
         );
-        let last_chunk = chunks.last().unwrap();
-        let mut chunk_to_pad = last_chunk.clone();
-        chunk_to_pad.is_padding = true;
-        let take_num = MAX_AGG_SNARKS - chunks.len();
-        chunks.extend(std::iter::repeat(chunk_to_pad).take(take_num));
+        run_report::record(&ReportRecord::Batch(BatchRecord {
+            batch_id: None,
+            num_chunks: batch.len(),
+            begin_block: *batch_begin_block,
+            end_block: block_num,
+            blob_utilization,
+            outcome: Outcome::not_run(),
+        }));
+        *batch_begin_block = block_num + 1;
     }
 }
 
+// Follow the chain tip, building chunks and batches as new blocks arrive, until ctrl-c.
+async fn follow_tip(
+    source: &Arc<ChainSource>,
+    begin_block: i64,
+    prefetch_config: PrefetchConfig,
+    poll_interval: Duration,
+    chunk_limits: &ChunkLimits,
+    batch_limits: &BatchLimits,
+) {
+    let mut chunk_builder = ChunkBuilder::new(chunk_limits.clone());
+    let mut batch_builder = BatchBuilder::new(batch_limits.clone());
+    let mut next_block = if begin_block == 0 {
+        source.get_block_number().await.unwrap() as i64
+    } else {
+        begin_block
+    };
+    let mut batch_begin_block = next_block;
+    // Hash of the last handled block, checked against the parent hash of the next one.
+    let mut last_block_hash: Option<H256> = None;
+    let mut prefetcher = TracePrefetcher::new(source.clone(), prefetch_config);
+    let shutdown = tokio::signal::ctrl_c();
+    tokio::pin!(shutdown);
+
+    log::info!("chain_prover: following chain tip from block-{next_block}");
+    'follow: loop {
+        let latest_block = tokio::select! {
+            _ = &mut shutdown => break,
+            latest_block = source.get_block_number() => latest_block,
+        };
+        let latest_block = match latest_block {
+            Ok(latest_block) => latest_block as i64,
+            Err(e) => {
+                log::error!("chain_prover: failed to request latest block number: {e}");
+                next_block - 1
+            }
+        };
+        if latest_block < next_block {
+            tokio::select! {
+                _ = &mut shutdown => break,
+                _ = tokio::time::sleep(poll_interval) => continue,
+            }
+        }
+
+        log::info!("chain_prover: new blocks {next_block} to {latest_block}");
+        prefetcher.push_blocks(next_block..=latest_block);
+        let mut remaining_blocks = (latest_block - next_block + 1) as usize;
+        while remaining_blocks > 0 {
+            let (block_num, trace) = tokio::select! {
+                _ = &mut shutdown => break 'follow,
+                next = prefetcher.next() => next.unwrap(),
+            };
+            remaining_blocks -= 1;
+            let trace = match trace {
+                Ok(trace) => trace,
+                Err(e) => {
+                    // Blocks of a chunk must be consecutive, so retry from here at next poll.
+                    log::error!("chain_prover: failed to request l2geth block-trace API for block-{block_num}: {e}");
+                    prefetcher.discard(remaining_blocks);
+                    next_block = block_num;
+                    continue 'follow;
+                }
+            };
+
+            if last_block_hash.is_some_and(|hash| hash != trace.header.parent_hash) {
+                prefetcher.discard(remaining_blocks);
+                if let Some(first_trace) = chunk_builder.pending().first() {
+                    let first_block = first_trace.header.number.unwrap().as_u64() as i64;
+                    log::warn!(
+                        "chain_prover: reorg detected at block-{block_num}, rebuilding pending chunk from block-{first_block}"
+                    );
+                    last_block_hash = Some(first_trace.header.parent_hash);
+                    next_block = first_block;
+                    chunk_builder.reset();
+                    continue 'follow;
+                }
+                // The reorged blocks are part of built chunks already, which can't be undone.
+                log::error!(
+                    "chain_prover: reorg detected at block-{block_num} below built chunks, continuing on the new chain"
+                );
+            }
+            last_block_hash = trace.header.hash;
+            next_block = block_num + 1;
+
+            if let Some(chunk) = chunk_builder.add(trace) {
+                build_chunk(chunk, &mut batch_builder, &mut batch_begin_block, block_num);
+            }
+        }
+    }
+
+    log::info!(
+        "chain_prover: shutting down at block-{next_block}, giving up {} pending blocks and {} pending chunks",
+        chunk_builder.pending().len(),
+        batch_builder.pending().len(),
+    );
+    prefetcher.log_stats("chain_prover: follow");
+}
+
 fn prove_chunk(
     batch_id: u64,
     chunk_id: u64,
@@ -247,87 +339,448 @@ fn prove_chunk(
         total_gas
     );
 
-    if env::var("CIRCUIT").unwrap_or_default() == "none" {
-        return None;
-    }
-    if env::var("CIRCUIT").unwrap_or_default() == "ccc" {
-        let ccc_modes = [CCCMode::Optimal];
-        run_circuit_capacity_checker(batch_id, chunk_id, &block_traces, &ccc_modes);
+    let circuit = env::var("CIRCUIT").unwrap_or_default();
+    if circuit == "none" || circuit == "ccc" {
+        let (row_usage, outcome) = if circuit == "ccc" {
+            let start = Instant::now();
+            let (row_usage, _) = ccc_by_chunk(batch_id, chunk_id, &block_traces);
+            (Some(row_usage), Outcome::succeeded(start.elapsed()))
+        } else {
+            (None, Outcome::not_run())
+        };
+        run_report::record(&ReportRecord::Chunk(ChunkRecord::new(
+            batch_id,
+            chunk_id,
+            &block_traces,
+            row_usage,
+            outcome,
+        )));
         return None;
     }
 
+    let mut record = ChunkRecord::new(batch_id, chunk_id, &block_traces, None, Outcome::not_run());
+    let start = Instant::now();
     let chunk_proof = prove_utils::prove_chunk(
         &format!("chain_prover: batch-{batch_id} chunk-{chunk_id}"),
         block_traces,
     );
+    record.outcome = Outcome::new(&chunk_proof, start.elapsed());
+    run_report::record(&ReportRecord::Chunk(record));
     log::info!("proving chunk done");
-    chunk_proof
+    chunk_proof.ok().flatten()
 }
 
 // Use constructed chunk/batch info from coordinator
 async fn prove_by_batch(
-    l2geth: &l2geth::Client,
-    rollupscan: &rollupscan_client::Client,
+    source: &Arc<ChainSource>,
     begin_batch: i64,
     end_batch: i64,
+    checkpointer: &Checkpointer,
+    prefetch_config: PrefetchConfig,
+    ledger: &FailureLedger,
+    chunk_workers: usize,
 ) {
-    for batch_id in begin_batch..=end_batch {
-        let chunks = rollupscan
-                .get_chunk_info_by_batch_index(batch_id)
-                .await
-                .unwrap_or_else(|e| {
-                    panic!("chain_prover: failed to request rollupscan chunks API for batch-{batch_id}: {e}")
-                });
+    let mut prefetcher = TracePrefetcher::new(source.clone(), prefetch_config);
+    let mut checkpoint = checkpointer.load::<BatchProveCheckpoint>();
+    let begin_batch = checkpoint.as_ref().map_or(begin_batch, |c| c.batch_id);
+    #[cfg(feature = "batch-prove")]
+    let mut parent_batch_hash = None;
+    // Bundles are not checkpointed, a resumed run starts a new bundle.
+    #[cfg(feature = "bundle-prove")]
+    let mut bundler = bundler::Bundler::new(
+        &prover::read_env_var("SCROLL_PROVER_PARAMS_DIR", "./test_params".to_string()),
+        &prover::read_env_var("BUNDLE_OUTPUT_DIR", "./output_bundle".to_string()),
+        prover::read_env_var("BUNDLE_SIZE", 0usize),
+    );
+    let mut gate = ContinuityGate::default();
+    'batch: for batch_id in begin_batch..=end_batch {
+        let BatchProveCheckpoint {
+            chunks_done,
+            mut chunk_proofs,
+            mut batch_meta,
+            ..
+        } = checkpoint
+            .take()
+            .unwrap_or_else(|| BatchProveCheckpoint::new(batch_id));
+        let chunks = match source.get_chunk_info_by_batch_index(batch_id).await {
+            Ok(chunks) => chunks,
+            Err(e) => {
+                ledger.fail(
+                    FailedItem::Batch { batch_id },
+                    e.context(format!(
+                        "chain_prover: failed to request rollupscan chunks API for batch-{batch_id}"
+                    )),
+                );
+                checkpointer.save(&BatchProveCheckpoint::new(batch_id + 1));
+                continue;
+            }
+        };
 
         if chunks.is_none() {
             log::warn!("chain_prover: no chunks in batch-{batch_id}");
             continue;
         }
 
-        let mut chunk_proofs = vec![];
-        for chunk in chunks.unwrap() {
+        let chunks = chunks.unwrap();
+        #[cfg(feature = "batch-prove")]
+        let (num_chunks, begin_block, end_block) = (
+            chunks.len(),
+            chunks.first().map_or(0, |chunk| chunk.start_block_number),
+            chunks.last().map_or(0, |chunk| chunk.end_block_number),
+        );
+        let mut remaining_blocks = 0;
+        prefetcher.push_blocks(
+            chunks
+                .iter()
+                .skip(chunks_done)
+                .flat_map(|chunk| chunk.start_block_number..=chunk.end_block_number)
+                .inspect(|_| remaining_blocks += 1),
+        );
+        let mut pending_chunks = VecDeque::new();
+        for (chunk_idx, chunk) in chunks.into_iter().enumerate().skip(chunks_done) {
             let chunk_id = chunk.index as u64;
             log::info!("chain_prover: handling chunk {:?}", chunk_id);
 
             let mut block_traces: Vec<BlockTrace> = vec![];
-            for block_num in chunk.start_block_number..=chunk.end_block_number {
-                let trace = l2geth
-                        .get_block_trace_by_num(block_num, false)
-                        .await
-                        .unwrap_or_else(|e| {
-                            panic!("chain_prover: failed to request l2geth block-trace API for batch-{batch_id} chunk-{chunk_id} block-{block_num}: {e}")
-                        });
-
-                block_traces.push(trace);
+            for _ in chunk.start_block_number..=chunk.end_block_number {
+                let (block_num, trace) = prefetcher.next().await.unwrap();
+                remaining_blocks -= 1;
+                match trace {
+                    Ok(trace) => block_traces.push(trace),
+                    Err(e) => {
+                        ledger.fail(
+                            FailedItem::Batch { batch_id },
+                            e.context(format!("chain_prover: failed to request l2geth block-trace API for batch-{batch_id} chunk-{chunk_id} block-{block_num}")),
+                        );
+                        abandon_batch(
+                            &mut prefetcher,
+                            remaining_blocks,
+                            pending_chunks,
+                            checkpointer,
+                            batch_id,
+                        )
+                        .await;
+                        gate.reset();
+                        continue 'batch;
+                    }
+                }
+            }
+
+            if let Err(e) = gate.check(&block_traces, batch_meta.next_l1_queue_index()) {
+                ledger.fail(
+                    FailedItem::Batch { batch_id },
+                    anyhow::anyhow!("chain_prover: batch-{batch_id} chunk-{chunk_id} {e}"),
+                );
+                abandon_batch(
+                    &mut prefetcher,
+                    remaining_blocks,
+                    pending_chunks,
+                    checkpointer,
+                    batch_id,
+                )
+                .await;
+                gate.reset();
+                continue 'batch;
             }
 
-            let chunk_proof = prove_chunk(batch_id as u64, chunk_id, block_traces);
+            batch_meta.add_chunk(&block_traces);
+            let handle = tokio::task::spawn_blocking(move || {
+                prove_chunk(batch_id as u64, chunk_id, block_traces)
+            });
+            pending_chunks.push_back((chunk_idx, batch_meta.clone(), handle));
 
-            if let Some(chunk_proof) = chunk_proof {
-                chunk_proofs.push(chunk_proof);
+            while pending_chunks.len() >= chunk_workers {
+                collect_chunk_proof(
+                    &mut pending_chunks,
+                    batch_id,
+                    &mut chunk_proofs,
+                    checkpointer,
+                )
+                .await;
             }
         }
+        while !pending_chunks.is_empty() {
+            collect_chunk_proof(
+                &mut pending_chunks,
+                batch_id,
+                &mut chunk_proofs,
+                checkpointer,
+            )
+            .await;
+        }
 
         #[cfg(feature = "batch-prove")]
-        use prover::BatchHeader;
-        #[cfg(feature = "batch-prove")]
-        let batch_header = BatchHeader::<MAX_AGG_SNARKS>::default();
-        #[cfg(feature = "batch-prove")]
-        prove_utils::prove_batch(
-            &format!("chain_prover: batch-{batch_id}"),
-            chunk_proofs,
-            batch_header,
+        {
+            let chain_batch = chain_batch_info(source, batch_id).await;
+            let (batch_hash, batch) = build_batch(
+                source,
+                batch_id,
+                num_chunks,
+                chunk_proofs,
+                &batch_meta,
+                parent_batch_hash,
+                chain_batch.as_ref().map(|batch| batch.hash),
+            )
+            .await;
+            parent_batch_hash = batch_hash;
+            let blob_utilization = batch
+                .as_ref()
+                .ok()
+                .map(|batch| batch.blob_bytes.len() as f64 / constants::N_BLOB_BYTES as f64);
+
+            let start = Instant::now();
+            #[cfg(not(feature = "bundle-prove"))]
+            let result = batch.and_then(|batch| {
+                prove_utils::prove_batch(&format!("chain_prover: batch-{batch_id}"), batch)
+            });
+            #[cfg(feature = "bundle-prove")]
+            let result = batch.and_then(|batch| bundler.prove_batch(batch_id, batch));
+            run_report::record(&ReportRecord::Batch(BatchRecord {
+                batch_id: Some(batch_id),
+                num_chunks,
+                begin_block,
+                end_block,
+                blob_utilization,
+                outcome: Outcome::new(&result, start.elapsed()),
+            }));
+
+            #[cfg(feature = "bundle-prove")]
+            bundler.add_batch(
+                batch_id,
+                result.ok(),
+                chain_batch.and_then(|batch| batch.bundle_hash),
+            );
+        }
+
+        checkpointer.save(&BatchProveCheckpoint::new(batch_id + 1));
+    }
+    #[cfg(feature = "bundle-prove")]
+    bundler.finish();
+    prefetcher.log_stats("chain_prover: batch_prove");
+    checkpointer.clear();
+}
+
+/// L1 message queue and roots following the last checked chunk, to gate proving on the next
+/// chunk following it. Traces of the chunk are sanity checked as well.
+#[derive(Default)]
+struct ContinuityGate {
+    next_l1_queue_index: Option<u64>,
+    last_block: Option<Roots>,
+}
+
+impl ContinuityGate {
+    /// Checks the chunk following the last checked one. `next_l1_queue_index` is the expected
+    /// queue index if known elsewhere, e.g. from a checkpoint.
+    fn check(
+        &mut self,
+        traces: &[BlockTrace],
+        next_l1_queue_index: Option<u64>,
+    ) -> Result<(), String> {
+        let mut violations = vec![];
+        match check_l1_queue(traces, next_l1_queue_index.or(self.next_l1_queue_index)) {
+            Ok((next, skipped)) => {
+                for skipped in skipped {
+                    log::warn!("chain_prover: {skipped}");
+                }
+                self.next_l1_queue_index = Some(next)
+            }
+            Err(queue_violations) => {
+                violations.extend(queue_violations.iter().map(ToString::to_string))
+            }
+        }
+        let roots = self
+            .last_block
+            .take()
+            .into_iter()
+            .chain(traces.iter().map(Roots::from_block))
+            .collect_vec();
+        violations.extend(check_roots(&roots).iter().map(ToString::to_string));
+        self.last_block = roots.last().cloned();
+        violations.extend(check_traces(traces, None).iter().map(ToString::to_string));
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            self.reset();
+            Err(format!(
+                "breaks L1 message queue or root continuity, or has bad traces: {}",
+                violations.join("; ")
+            ))
+        }
+    }
+
+    /// Forgets the last checked chunk, e.g. after dropping chunks.
+    fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+// A chunk being proven by a worker, with the batch meta up to and including it.
+type PendingChunk = (usize, BatchMeta, JoinHandle<Option<ChunkProofV2>>);
+
+// Discard the remaining blocks of the batch and move the checkpoint to the next batch.
+async fn abandon_batch(
+    prefetcher: &mut TracePrefetcher,
+    remaining_blocks: usize,
+    pending_chunks: VecDeque<PendingChunk>,
+    checkpointer: &Checkpointer,
+    batch_id: i64,
+) {
+    prefetcher.discard(remaining_blocks);
+    // Let running workers finish, so that no more than `chunk_workers` chunks are proven at once.
+    for (_, _, handle) in pending_chunks {
+        let _ = handle.await;
+    }
+    checkpointer.save(&BatchProveCheckpoint::new(batch_id + 1));
+}
+
+// Wait for the oldest pending chunk, so that proofs are collected in chunk order, and checkpoint
+// the batch progress up to it.
+async fn collect_chunk_proof(
+    pending_chunks: &mut VecDeque<PendingChunk>,
+    batch_id: i64,
+    chunk_proofs: &mut Vec<ChunkProofV2>,
+    checkpointer: &Checkpointer,
+) {
+    let (chunk_idx, batch_meta, handle) = pending_chunks.pop_front().unwrap();
+    let chunk_proof = handle.await.unwrap_or_else(|e| {
+        log::error!("chain_prover: batch-{batch_id} chunk worker failed: {e}");
+        None
+    });
+
+    if let Some(chunk_proof) = chunk_proof {
+        chunk_proofs.push(chunk_proof);
+    }
+    checkpointer.save(&BatchProveCheckpoint {
+        batch_id,
+        chunks_done: chunk_idx + 1,
+        chunk_proofs: chunk_proofs.clone(),
+        batch_meta,
+    });
+}
+
+// Batch info reported by the chain, `None` if not available.
+#[cfg(feature = "batch-prove")]
+async fn chain_batch_info(source: &ChainSource, batch_index: i64) -> Option<BatchInfo> {
+    source
+        .get_batch_info(batch_index)
+        .await
+        .unwrap_or_else(|e| {
+            log::error!("chain_prover: failed to request info of batch-{batch_index}: {e}");
+            None
+        })
+}
+
+// Build the batch header from chunk proofs and chain data, and the batch proving task, which fails
+// if some chunks are not proven. Also returns the hash to be used as parent batch hash of the
+// next batch.
+#[cfg(feature = "batch-prove")]
+async fn build_batch(
+    source: &ChainSource,
+    batch_id: i64,
+    num_chunks: usize,
+    chunk_proofs: Vec<ChunkProofV2>,
+    batch_meta: &BatchMeta,
+    parent_batch_hash: Option<H256>,
+    expected_batch_hash: Option<H256>,
+) -> (Option<H256>, Result<BatchProvingTask, String>) {
+    use integration::{
+        codec::BatchCodec, kzg::blob_commitment, prove::get_blob_from_chunks,
+        root_check::check_chunk_proof_roots,
+    };
+
+    if chunk_proofs.len() != num_chunks {
+        let err = format!(
+            "chain_prover: batch-{batch_id} has {} chunk proofs for {num_chunks} chunks, skip batch proving",
+            chunk_proofs.len()
         );
+        log::error!("{err}");
+        return (expected_batch_hash, Err(err));
     }
+    // Chunk proofs may come from a checkpoint, check them again before aggregation.
+    let violations = check_chunk_proof_roots(&chunk_proofs);
+    if !violations.is_empty() {
+        let err = format!(
+            "chain_prover: batch-{batch_id} chunk proofs don't chain: {}",
+            violations.iter().join("; ")
+        );
+        log::error!("{err}");
+        return (expected_batch_hash, Err(err));
+    }
+
+    let parent_batch_hash = match parent_batch_hash {
+        Some(hash) => hash,
+        None => chain_batch_info(source, batch_id - 1)
+            .await
+            .map(|batch| batch.hash)
+            .unwrap_or_else(|| {
+                log::warn!(
+                    "chain_prover: hash of batch-{} not available, use zero parent batch hash",
+                    batch_id - 1
+                );
+                H256::zero()
+            }),
+    };
+    let chunk_infos = chunk_proofs
+        .iter()
+        .map(|p| p.inner.chunk_info().clone())
+        .collect_vec();
+    let codec: BatchCodec = prover::read_env_var("BATCH_CODEC", "v4".to_string())
+        .parse()
+        .unwrap();
+    let blob_bytes = match get_blob_from_chunks(codec, &chunk_infos) {
+        Ok(blob_bytes) => blob_bytes,
+        Err(e) => {
+            let err = format!("chain_prover: batch-{batch_id} blob encoding failed: {e:#}");
+            log::error!("{err}");
+            return (expected_batch_hash, Err(err));
+        }
+    };
+    let batch_header = batch_meta.build_header(
+        codec.version(),
+        batch_id as u64,
+        parent_batch_hash,
+        &chunk_infos,
+        &blob_bytes,
+    );
+    // Check the blob encoding against c-kzg before spending time on proving.
+    match blob_commitment(codec, &chunk_infos) {
+        Ok(commitment) => log::info!(
+            "chain_prover: batch-{batch_id} blob versioned hash {:?}, challenge {:?}, evaluation {:?}",
+            commitment.versioned_hash,
+            commitment.challenge,
+            commitment.evaluation
+        ),
+        Err(e) => {
+            let err = format!("chain_prover: batch-{batch_id} blob commitment failed: {e:#}");
+            log::error!("{err}");
+            return (expected_batch_hash, Err(err));
+        }
+    }
+    let batch_hash = batch_header.batch_hash();
+    match expected_batch_hash {
+        Some(expected) if expected != batch_hash => log::error!(
+            "chain_prover: batch-{batch_id} hash mismatch, built {batch_hash:?}, chain {expected:?}"
+        ),
+        Some(_) => log::info!("chain_prover: batch-{batch_id} hash {batch_hash:?} matches chain"),
+        None => log::warn!(
+            "chain_prover: batch-{batch_id} hash {batch_hash:?} not checked, chain value not available"
+        ),
+    }
+
+    let batch = BatchProvingTask {
+        chunk_proofs,
+        batch_header,
+        blob_bytes,
+    };
+
+    // Chain the next batch to the real parent, so that a mismatch doesn't spread.
+    (Some(expected_batch_hash.unwrap_or(batch_hash)), Ok(batch))
 }
 
-async fn txtx_ccc(l2geth: &l2geth::Client, begin_block: i64, end_block: i64) {
+async fn txtx_ccc(source: &ChainSource, begin_block: i64, end_block: i64, ledger: &FailureLedger) {
     let (begin_block, end_block) = if begin_block == 0 && end_block == 0 {
-        // Blocks within last 24 hours
-        let block_num = 24 * 1200;
-        log::info!("use latest {block_num} blocks");
-        let latest_block = l2geth.get_block_number().await.unwrap();
-        (latest_block as i64 - block_num, latest_block as i64)
+        source.get_default_block_range().await.unwrap()
     } else {
         (begin_block, end_block)
     };
@@ -336,18 +789,26 @@ async fn txtx_ccc(l2geth: &l2geth::Client, begin_block: i64, end_block: i64) {
         let block_num = block_num as u64;
         let batch_id = block_num;
         let chunk_id = block_num;
-        let trace = l2geth
-        .get_block_trace_by_num(block_num as i64, false)
-        .await
-        .unwrap_or_else(|e| {
-            panic!("chain_prover: failed to request l2geth block-trace API for block-{block_num}: {e}")
-        });
-        let tx_traces = l2geth
-        .get_txbytx_trace_by_num(block_num as i64)
-        .await
-        .unwrap_or_else(|e| {
-            panic!("chain_prover: failed to request l2geth block-trace API for block-{block_num}: {e}")
-        });
+        let traces = async {
+            anyhow::Ok((
+                source.get_block_trace_by_num(block_num as i64).await?,
+                source.get_txbytx_trace_by_num(block_num as i64).await?,
+            ))
+        };
+        let (trace, tx_traces) = match traces.await {
+            Ok(traces) => traces,
+            Err(e) => {
+                ledger.fail(
+                    FailedItem::Block {
+                        block_num: block_num as i64,
+                    },
+                    e.context(format!(
+                        "chain_prover: failed to request l2geth block-trace API for block-{block_num}"
+                    )),
+                );
+                continue;
+            }
+        };
         let (real_usage, t) = ccc_by_chunk(batch_id, chunk_id, &[trace]);
 
         // part2: tx by tx row usage
@@ -378,6 +839,169 @@ async fn txtx_ccc(l2geth: &l2geth::Client, begin_block: i64, end_block: i64) {
     }
 }
 
+// Re-process the blocks and batches recorded in the failure ledger by previous runs.
+// Failed blocks are proved as single-block chunks, and failed block ranges as one chunk each.
+async fn retry_failures(
+    source: &Arc<ChainSource>,
+    prefetch_config: PrefetchConfig,
+    ledger: &FailureLedger,
+    chunk_workers: usize,
+) {
+    let num_records = ledger
+        .start_retry()
+        .unwrap_or_else(|e| panic!("chain_prover: failed to read failure ledger: {e}"));
+    log::info!("chain_prover: re-processing {num_records} failed items");
+
+    // No checkpoints, the ledger itself tracks what is left.
+    let checkpointer = Checkpointer::new("", "");
+    while let Some(record) = ledger.next_retry() {
+        log::info!(
+            "chain_prover: re-processing {:?}, previous error: {}",
+            record.item,
+            record.error
+        );
+        match record.item {
+            FailedItem::Batch { batch_id } => {
+                prove_by_batch(
+                    source,
+                    batch_id,
+                    batch_id,
+                    &checkpointer,
+                    prefetch_config,
+                    ledger,
+                    chunk_workers,
+                )
+                .await
+            }
+            FailedItem::Block { block_num } => {
+                match source.get_block_trace_by_num(block_num).await {
+                    Ok(trace) => {
+                        prove_chunk(0, block_num as u64, vec![trace]);
+                    }
+                    Err(e) => ledger.fail(
+                        record.item,
+                        e.context(format!(
+                            "chain_prover: failed to request l2geth block-trace API for block-{block_num}"
+                        )),
+                    ),
+                }
+            }
+            FailedItem::BlockRange {
+                begin_block,
+                end_block,
+            } => {
+                let block_nums = (begin_block..=end_block).collect_vec();
+                let traces = source
+                    .get_block_traces_by_nums(&block_nums)
+                    .await
+                    .into_iter()
+                    .map(|(block_num, trace)| {
+                        trace.map_err(|e| {
+                            e.context(format!(
+                                "chain_prover: failed to request l2geth block-trace API for block-{block_num}"
+                            ))
+                        })
+                    })
+                    .collect::<anyhow::Result<Vec<_>>>();
+                match traces {
+                    Ok(traces) => {
+                        prove_chunk(0, begin_block as u64, traces);
+                    }
+                    Err(e) => ledger.fail(record.item, e),
+                }
+            }
+        }
+    }
+    if let Err(e) = ledger.finish_retry() {
+        log::error!("chain_prover: failed to rewrite failure ledger: {e}");
+    }
+}
+
+// Pick `count` distinct random numbers of `begin..=end` in ascending order, reproducible from
+// `seed`.
+fn sample_range(begin: i64, end: i64, count: usize, seed: u64) -> Vec<i64> {
+    let mut rng = XorShiftRng::seed_from_u64(seed);
+    let len = (end - begin + 1).max(0) as usize;
+    let mut samples = rand::seq::index::sample(&mut rng, len, count.min(len))
+        .into_iter()
+        .map(|i| begin + i as i64)
+        .collect::<Vec<_>>();
+    samples.sort_unstable();
+    samples
+}
+
+// Handle `count` random blocks of the range, each as a single-block chunk.
+async fn sample_blocks(
+    source: &Arc<ChainSource>,
+    begin_block: i64,
+    end_block: i64,
+    count: usize,
+    seed: u64,
+    prefetch_config: PrefetchConfig,
+    ledger: &FailureLedger,
+) {
+    let (begin_block, end_block) = if begin_block == 0 && end_block == 0 {
+        source.get_default_block_range().await.unwrap()
+    } else {
+        (begin_block, end_block)
+    };
+    let blocks = sample_range(begin_block, end_block, count, seed);
+    log::info!(
+        "chain_prover: sampled {} blocks of {begin_block} to {end_block} with seed {seed}: {blocks:?}",
+        blocks.len()
+    );
+
+    let mut prefetcher = TracePrefetcher::new(source.clone(), prefetch_config);
+    prefetcher.push_blocks(blocks);
+    while let Some((block_num, trace)) = prefetcher.next().await {
+        match trace {
+            Ok(trace) => {
+                prove_chunk(0, block_num as u64, vec![trace]);
+            }
+            Err(e) => ledger.fail(
+                FailedItem::Block { block_num },
+                e.context(format!(
+                    "chain_prover: failed to request l2geth block-trace API for block-{block_num}"
+                )),
+            ),
+        }
+    }
+    prefetcher.log_stats("chain_prover: sample_blocks");
+}
+
+// Handle `count` random batches of the range, same as `prove_by_batch` on each.
+async fn sample_batches(
+    source: &Arc<ChainSource>,
+    begin_batch: i64,
+    end_batch: i64,
+    count: usize,
+    seed: u64,
+    prefetch_config: PrefetchConfig,
+    ledger: &FailureLedger,
+    chunk_workers: usize,
+) {
+    let batches = sample_range(begin_batch, end_batch, count, seed);
+    log::info!(
+        "chain_prover: sampled {} batches of {begin_batch} to {end_batch} with seed {seed}: {batches:?}",
+        batches.len()
+    );
+
+    // Samples are cheap to redo, no checkpoints.
+    let checkpointer = Checkpointer::new("", "");
+    for batch_id in batches {
+        prove_by_batch(
+            source,
+            batch_id,
+            batch_id,
+            &checkpointer,
+            prefetch_config,
+            ledger,
+            chunk_workers,
+        )
+        .await;
+    }
+}
+
 #[tokio::main]
 async fn main() {
     init_env_and_log("chain_prover");
@@ -389,18 +1013,189 @@ async fn main() {
 
     warmup();
 
-    let l2geth = l2geth::Client::new("chain_prover", &setting.l2geth_api_url)
-        .unwrap_or_else(|e| panic!("chain_prover: failed to initialize ethers Provider: {e}"));
-    let rollupscan = rollupscan_client::Client::new("chain_prover", &setting.rollupscan_api_url);
+    let source = if setting.replay_trace_dir.is_empty() {
+        let retry_config = RetryConfig {
+            max_retries: setting.rpc_max_retries,
+            initial_backoff: Duration::from_millis(setting.rpc_retry_backoff_ms),
+            ..Default::default()
+        };
+        let l2geth = l2geth::Client::new("chain_prover", &setting.l2geth_api_url)
+            .unwrap_or_else(|e| panic!("chain_prover: failed to initialize ethers Provider: {e}"))
+            .with_retry(retry_config)
+            .with_batch_size(setting.rpc_batch_size);
+        let l2geth = if setting.trace_cache_dir.is_empty() {
+            l2geth
+        } else {
+            l2geth.with_cache(TraceCache::new(
+                &setting.trace_cache_dir,
+                setting.trace_cache_mode,
+            ))
+        };
+        let l2geth = match setting.chain_id {
+            Some(chain_id) => l2geth.with_chain_id(chain_id),
+            None => l2geth,
+        };
+        let rollupscan =
+            rollupscan_client::Client::new("chain_prover", &setting.rollupscan_api_url)
+                .with_retry(retry_config);
+        #[cfg(feature = "batch-prove")]
+        let rollupscan = rollupscan.with_batch_url(&setting.rollupscan_batch_api_url);
+        ChainSource::Live {
+            l2geth,
+            rollupscan,
+            trace_options: setting.trace_options.clone(),
+        }
+    } else {
+        let replay = replay_client::Client::new("chain_prover", &setting.replay_trace_dir)
+            .unwrap_or_else(|e| panic!("chain_prover: failed to load replay trace dir: {e}"));
+        ChainSource::Replay(replay)
+    };
+    let source = Arc::new(source);
+    let prefetch_config = PrefetchConfig {
+        window: setting.prefetch_window,
+        concurrency: setting.prefetch_concurrency,
+        batch_size: setting.rpc_batch_size,
+    };
+    let ledger = FailureLedger::new(&setting.failure_ledger, setting.failure_policy);
+    run_report::init(&setting.run_report);
+    let chunk_workers = setting.chunk_workers();
+    log::info!("chain_prover: proving up to {chunk_workers} chunks at once");
+    #[cfg(feature = "chunk-prove")]
+    if chunk_workers > 1 {
+        prove_utils::init_chunk_prover_pool(
+            &prover::read_env_var("SCROLL_PROVER_PARAMS_DIR", "./test_params".to_string()),
+            &prover::read_env_var("SCROLL_PROVER_ASSETS_DIR", "./test_assets".to_string()),
+        );
+    }
 
     let test_mode = &setting.test_mode;
 
     if test_mode == "batch_prove" {
-        prove_by_batch(&l2geth, &rollupscan, setting.begin_batch, setting.end_batch).await
+        let checkpointer = Checkpointer::new(
+            &setting.checkpoint_dir,
+            &format!("batch_prove_{}_{}", setting.begin_batch, setting.end_batch),
+        );
+        prove_by_batch(
+            &source,
+            setting.begin_batch,
+            setting.end_batch,
+            &checkpointer,
+            prefetch_config,
+            &ledger,
+            chunk_workers,
+        )
+        .await
     } else if test_mode == "block_prove" {
-        prove_by_block(&l2geth, setting.begin_block, setting.end_block).await
+        let checkpointer = Checkpointer::new(
+            &setting.checkpoint_dir,
+            &format!("block_prove_{}_{}", setting.begin_block, setting.end_block),
+        );
+        prove_by_block(
+            &source,
+            setting.begin_block,
+            setting.end_block,
+            &checkpointer,
+            prefetch_config,
+            &ledger,
+            &setting.chunk_limits,
+            &setting.batch_limits,
+        )
+        .await
+    } else if test_mode == "follow" {
+        follow_tip(
+            &source,
+            setting.begin_block,
+            prefetch_config,
+            Duration::from_millis(setting.follow_poll_interval_ms),
+            &setting.chunk_limits,
+            &setting.batch_limits,
+        )
+        .await
+    } else if test_mode == "sample_blocks" || test_mode == "sample_batches" {
+        let seed = setting.sample_seed.unwrap_or_else(rand::random);
+        log::info!("chain_prover: sampling seed {seed}, set SAMPLE_SEED={seed} to reproduce");
+        if test_mode == "sample_blocks" {
+            sample_blocks(
+                &source,
+                setting.begin_block,
+                setting.end_block,
+                setting.sample_count,
+                seed,
+                prefetch_config,
+                &ledger,
+            )
+            .await
+        } else {
+            sample_batches(
+                &source,
+                setting.begin_batch,
+                setting.end_batch,
+                setting.sample_count,
+                seed,
+                prefetch_config,
+                &ledger,
+                chunk_workers,
+            )
+            .await
+        }
+    } else if test_mode == "diff_chunking" {
+        chunking_diff::diff_chunking(
+            &source,
+            setting.begin_batch,
+            setting.end_batch,
+            prefetch_config,
+            &ledger,
+            &setting.chunk_limits,
+            &setting.batch_limits,
+        )
+        .await
+    } else if test_mode == "simulate" {
+        proposer_sim::simulate(
+            &source,
+            setting.begin_block,
+            setting.end_block,
+            prefetch_config,
+            &ledger,
+            setting
+                .sweep
+                .grid(&setting.chunk_limits, &setting.batch_limits),
+        )
+        .await
+    } else if test_mode == "check_roots" {
+        // Comma separated chunk proof files in chunk order, checked instead of the batch range.
+        let proof_files = prover::read_env_var("CHUNK_PROOF_FILES", String::new());
+        if proof_files.is_empty() {
+            root_checker::check_batches(
+                &source,
+                setting.begin_batch,
+                setting.end_batch,
+                prefetch_config,
+                &ledger,
+            )
+            .await
+        } else {
+            root_checker::check_proof_files(&proof_files.split(',').map(String::from).collect_vec())
+        }
+    } else if test_mode == "check_trace_cache" {
+        let cache = TraceCache::new(&setting.trace_cache_dir, setting.trace_cache_mode);
+        let broken = cache
+            .check_integrity()
+            .unwrap_or_else(|e| panic!("chain_prover: failed to check trace cache: {e}"));
+        for (path, reason) in &broken {
+            log::error!(
+                "chain_prover: broken trace cache file {}: {reason}",
+                path.display()
+            );
+        }
+        log::info!(
+            "chain_prover: {} broken files in trace cache {}",
+            broken.len(),
+            setting.trace_cache_dir
+        );
     } else if test_mode == "txtx_ccc" {
-        txtx_ccc(&l2geth, setting.begin_block, setting.end_block).await
+        txtx_ccc(&source, setting.begin_block, setting.end_block, &ledger).await
+    } else if test_mode == "retry_failures" {
+        retry_failures(&source, prefetch_config, &ledger, chunk_workers).await
     } else {
         // Handle unknown test_mode here
         unimplemented!("{test_mode}");
@@ -418,7 +1213,54 @@ struct Setting {
     end_block: i64,
     l2geth_api_url: String,
     rollupscan_api_url: String,
+    // Used to check built batch hashes against the chain, if not empty.
+    #[cfg(feature = "batch-prove")]
+    rollupscan_batch_api_url: String,
     test_mode: String,
+    // Empty means checkpointing is disabled.
+    checkpoint_dir: String,
+    // Replay traces from this dir instead of l2geth and rollupscan, if not empty.
+    replay_trace_dir: String,
+    // Cache l2geth traces in this dir, if not empty.
+    trace_cache_dir: String,
+    trace_cache_mode: CacheMode,
+    // Chain id of the cached traces, requested from l2geth if not set.
+    chain_id: Option<u64>,
+    // Options of l2geth traces, e.g. to re-trace history under an upcoming hardfork.
+    trace_options: TraceOptions,
+    // Max number of block traces fetched ahead of proving.
+    prefetch_window: usize,
+    // Max number of concurrent block trace requests.
+    prefetch_concurrency: usize,
+    // Max number of blocks in a JSON-RPC batch of block trace requests.
+    rpc_batch_size: usize,
+    // Retries of each l2geth and rollupscan request.
+    rpc_max_retries: u32,
+    // Backoff before the first retry, doubled for each following retry.
+    rpc_retry_backoff_ms: u64,
+    // "abort" or "skip" a block or batch whose requests still fail after retries.
+    failure_policy: FailurePolicy,
+    // Json lines file recording the failed blocks and batches, if not empty.
+    failure_ledger: String,
+    // Json lines file recording the result of each chunk, batch and bundle, if not empty.
+    run_report: String,
+    // Max number of chunks of a batch proven at the same time.
+    chunk_workers: usize,
+    // Memory available to chunk workers in GB, 0 means unlimited.
+    memory_budget_gb: usize,
+    // Peak memory of one chunk worker in GB.
+    chunk_worker_memory_gb: usize,
+    // Limits of chunks and batches built in block, follow, diff_chunking and simulate modes.
+    chunk_limits: ChunkLimits,
+    batch_limits: BatchLimits,
+    // Limits swept over in simulate mode, on top of the above.
+    sweep: SweepSpec,
+    // Interval between polls of the latest block in follow mode.
+    follow_poll_interval_ms: u64,
+    // Number of blocks or batches picked in sampling modes.
+    sample_count: usize,
+    // Seed of sampling modes, random if not set.
+    sample_seed: Option<u64>,
 }
 
 impl Setting {
@@ -427,6 +1269,8 @@ impl Setting {
             env::var("L2GETH_API_URL").unwrap_or("http://127.0.0.1:8545".to_string());
         let rollupscan_api_url = env::var("ROLLUPSCAN_API_URL");
         let rollupscan_api_url = rollupscan_api_url.unwrap_or_default();
+        #[cfg(feature = "batch-prove")]
+        let rollupscan_batch_api_url = env::var("ROLLUPSCAN_BATCH_API_URL").unwrap_or_default();
         let begin_batch = env::var("PROVE_BEGIN_BATCH")
             .ok()
             .and_then(|n| n.parse().ok())
@@ -447,6 +1291,115 @@ impl Setting {
             .ok()
             .and_then(|n| n.parse().ok())
             .unwrap_or_default();
+        let checkpoint_dir = env::var("CHECKPOINT_DIR").unwrap_or_default();
+        let replay_trace_dir = env::var("REPLAY_TRACE_DIR").unwrap_or_default();
+        let trace_cache_dir = env::var("TRACE_CACHE_DIR").unwrap_or_default();
+        let trace_cache_mode = env::var("TRACE_CACHE_MODE")
+            .ok()
+            .map(|m| m.parse().unwrap())
+            .unwrap_or_default();
+        let chain_id = env::var("CHAIN_ID").ok().and_then(|n| n.parse().ok());
+        let trace_options = TraceOptions {
+            storage_proof_format: env::var("TRACE_STORAGE_PROOF_FORMAT")
+                .ok()
+                .map(|f| f.parse().unwrap())
+                .unwrap_or_default(),
+            // e.g. "curie=1,darwin=0"
+            overrides: env::var("TRACE_HARDFORK_OVERRIDES")
+                .ok()
+                .map(|o| TraceOptions::parse_overrides(&o).unwrap())
+                .unwrap_or_default(),
+            extra: env::var("TRACE_EXTRA_OPTIONS")
+                .ok()
+                .map(|o| serde_json::from_str(&o).unwrap())
+                .unwrap_or_default(),
+        };
+        // Activates the hardfork and the earlier ones from the start, unless overridden above.
+        let trace_options = match env::var("TRACE_HARDFORK") {
+            Ok(hardfork) => trace_options.with_hardfork(hardfork.parse().unwrap()),
+            Err(_) => trace_options,
+        };
+        let prefetch_window = env::var("PREFETCH_WINDOW")
+            .ok()
+            .and_then(|n| n.parse().ok())
+            .unwrap_or(16);
+        let prefetch_concurrency = env::var("PREFETCH_CONCURRENCY")
+            .ok()
+            .and_then(|n| n.parse().ok())
+            .unwrap_or(4);
+        let rpc_batch_size = env::var("RPC_BATCH_SIZE")
+            .ok()
+            .and_then(|n| n.parse().ok())
+            .unwrap_or(1);
+        let rpc_max_retries = env::var("RPC_MAX_RETRIES")
+            .ok()
+            .and_then(|n| n.parse().ok())
+            .unwrap_or(5);
+        let rpc_retry_backoff_ms = env::var("RPC_RETRY_BACKOFF_MS")
+            .ok()
+            .and_then(|n| n.parse().ok())
+            .unwrap_or(1000);
+        let failure_policy = env::var("FAILURE_POLICY")
+            .ok()
+            .map(|p| p.parse().unwrap())
+            .unwrap_or(FailurePolicy::Abort);
+        let failure_ledger = env::var("FAILURE_LEDGER").unwrap_or_default();
+        let run_report = env::var("RUN_REPORT").unwrap_or_default();
+        let chunk_workers = env::var("CHUNK_WORKERS")
+            .ok()
+            .and_then(|n| n.parse().ok())
+            .unwrap_or(1);
+        let memory_budget_gb = env::var("MEMORY_BUDGET_GB")
+            .ok()
+            .and_then(|n| n.parse().ok())
+            .unwrap_or_default();
+        let chunk_worker_memory_gb = env::var("CHUNK_WORKER_MEMORY_GB")
+            .ok()
+            .and_then(|n| n.parse().ok())
+            .unwrap_or(300);
+        let default_batch_limits = BatchLimits::default();
+        let chunk_limits = ChunkLimits {
+            max_blocks: env::var("CHUNK_MAX_BLOCKS")
+                .ok()
+                .and_then(|n| n.parse().ok()),
+            max_gas: env::var("CHUNK_MAX_GAS").ok().and_then(|n| n.parse().ok()),
+            max_l1_messages: env::var("CHUNK_MAX_L1_MESSAGES")
+                .ok()
+                .and_then(|n| n.parse().ok()),
+            max_tx_payload_bytes: env::var("CHUNK_MAX_TX_PAYLOAD_BYTES")
+                .ok()
+                .and_then(|n| n.parse().ok()),
+            max_rows: env::var("CHUNK_MAX_ROWS").ok().and_then(|n| n.parse().ok()),
+            ccc_mode: match env::var("CHUNK_CCC_MODE").as_deref() {
+                Ok("none") => None,
+                Ok(mode) => Some(mode.parse().unwrap()),
+                Err(_) => ChunkLimits::default().ccc_mode,
+            },
+        };
+        let batch_limits = BatchLimits {
+            max_chunks: env::var("BATCH_MAX_CHUNKS")
+                .ok()
+                .and_then(|n| n.parse().ok())
+                .unwrap_or(default_batch_limits.max_chunks),
+            max_blob_bytes: env::var("BATCH_MAX_BLOB_BYTES")
+                .ok()
+                .and_then(|n| n.parse().ok())
+                .unwrap_or(default_batch_limits.max_blob_bytes),
+            codec: env::var("BATCH_CODEC")
+                .ok()
+                .map(|codec| codec.parse().unwrap())
+                .unwrap_or(default_batch_limits.codec),
+        };
+        let sweep = SweepSpec::from_env();
+        let follow_poll_interval_ms = env::var("FOLLOW_POLL_INTERVAL_MS")
+            .ok()
+            .and_then(|n| n.parse().ok())
+            .unwrap_or(3000);
+        let sample_count = env::var("SAMPLE_COUNT")
+            .ok()
+            .and_then(|n| n.parse().ok())
+            .unwrap_or(10);
+        let sample_seed = env::var("SAMPLE_SEED").ok().and_then(|n| n.parse().ok());
 
         Self {
             begin_batch,
@@ -455,7 +1408,49 @@ impl Setting {
             end_block,
             l2geth_api_url,
             rollupscan_api_url,
+            #[cfg(feature = "batch-prove")]
+            rollupscan_batch_api_url,
             test_mode,
+            checkpoint_dir,
+            replay_trace_dir,
+            trace_cache_dir,
+            trace_cache_mode,
+            chain_id,
+            trace_options,
+            prefetch_window,
+            prefetch_concurrency,
+            rpc_batch_size,
+            rpc_max_retries,
+            rpc_retry_backoff_ms,
+            failure_policy,
+            failure_ledger,
+            run_report,
+            chunk_workers,
+            memory_budget_gb,
+            chunk_worker_memory_gb,
+            chunk_limits,
+            batch_limits,
+            sweep,
+            follow_poll_interval_ms,
+            sample_count,
+            sample_seed,
+        }
+    }
+
+    /// Number of chunk workers, limited by the memory budget. At least 1.
+    pub fn chunk_workers(&self) -> usize {
+        let mut workers = self.chunk_workers;
+        if self.memory_budget_gb > 0 {
+            let affordable = self.memory_budget_gb / self.chunk_worker_memory_gb.max(1);
+            if affordable < workers {
+                log::warn!(
+                    "chain_prover: memory budget {}GB only affords {affordable} chunk workers of {}GB",
+                    self.memory_budget_gb,
+                    self.chunk_worker_memory_gb
+                );
+                workers = affordable;
+            }
         }
+        workers.max(1)
     }
 }
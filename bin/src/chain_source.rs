@@ -0,0 +1,117 @@
+use crate::{replay_client, rollupscan_client};
+use anyhow::Result;
+use integration::{l2geth, trace_options::TraceOptions};
+use prover::eth_types::l2_types::BlockTrace;
+
+#[cfg(feature = "batch-prove")]
+use crate::rollupscan_client::BatchInfo;
+
+/// Where `chain_prover` gets block traces and chunk layouts from.
+pub enum ChainSource {
+    /// Traces from l2geth under the trace options, chunk layouts from rollupscan.
+    Live {
+        l2geth: l2geth::Client,
+        rollupscan: rollupscan_client::Client,
+        trace_options: TraceOptions,
+    },
+    /// Both from a local trace dir, without any network access.
+    Replay(replay_client::Client),
+}
+
+impl ChainSource {
+    /// Block range used when no range is configured: blocks within last 24 hours for live
+    /// chains, all blocks of the trace dir for replay.
+    pub async fn get_default_block_range(&self) -> Result<(i64, i64)> {
+        match self {
+            Self::Live { l2geth, .. } => {
+                let block_num = 24 * 1200;
+                log::info!("use latest {block_num} blocks");
+                let latest_block = l2geth.get_block_number().await?;
+                Ok((latest_block as i64 - block_num, latest_block as i64))
+            }
+            Self::Replay(replay) => replay.get_block_range(),
+        }
+    }
+
+    /// Latest block of the chain, or the last block of the trace dir for replay.
+    pub async fn get_block_number(&self) -> Result<u64> {
+        match self {
+            Self::Live { l2geth, .. } => l2geth.get_block_number().await,
+            Self::Replay(replay) => replay.get_block_number(),
+        }
+    }
+
+    pub async fn get_block_trace_by_num(&self, block_num: i64) -> Result<BlockTrace> {
+        match self {
+            Self::Live {
+                l2geth,
+                trace_options,
+                ..
+            } => {
+                l2geth
+                    .get_block_trace_by_num(block_num, trace_options)
+                    .await
+            }
+            Self::Replay(replay) => replay.get_block_trace_by_num(block_num),
+        }
+    }
+
+    /// Traces of the blocks in the order of `block_nums`, requested in JSON-RPC batches from
+    /// l2geth.
+    pub async fn get_block_traces_by_nums(
+        &self,
+        block_nums: &[i64],
+    ) -> Vec<(i64, Result<BlockTrace>)> {
+        match self {
+            Self::Live {
+                l2geth,
+                trace_options,
+                ..
+            } => {
+                l2geth
+                    .get_block_traces_by_nums(block_nums, trace_options)
+                    .await
+            }
+            Self::Replay(replay) => block_nums
+                .iter()
+                .map(|&block_num| (block_num, replay.get_block_trace_by_num(block_num)))
+                .collect(),
+        }
+    }
+
+    pub async fn get_txbytx_trace_by_num(&self, block_num: i64) -> Result<Vec<BlockTrace>> {
+        match self {
+            Self::Live {
+                l2geth,
+                trace_options,
+                ..
+            } => {
+                l2geth
+                    .get_txbytx_trace_by_num(block_num, trace_options)
+                    .await
+            }
+            Self::Replay(replay) => replay.get_txbytx_trace_by_num(block_num),
+        }
+    }
+
+    pub async fn get_chunk_info_by_batch_index(
+        &self,
+        batch_index: i64,
+    ) -> Result<Option<Vec<rollupscan_client::ChunkInfo>>> {
+        match self {
+            Self::Live { rollupscan, .. } => {
+                rollupscan.get_chunk_info_by_batch_index(batch_index).await
+            }
+            Self::Replay(replay) => replay.get_chunk_info_by_batch_index(batch_index),
+        }
+    }
+
+    /// Batch info reported by the chain, `None` if not available.
+    #[cfg(feature = "batch-prove")]
+    pub async fn get_batch_info(&self, batch_index: i64) -> Result<Option<BatchInfo>> {
+        match self {
+            Self::Live { rollupscan, .. } => rollupscan.get_batch_info_by_index(batch_index).await,
+            Self::Replay(_) => Ok(None),
+        }
+    }
+}
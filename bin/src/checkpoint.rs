@@ -0,0 +1,108 @@
+use crate::batch_meta::BatchMeta;
+use anyhow::Result;
+use prover::{eth_types::l2_types::BlockTrace, ChunkInfo, ChunkProofV2, RowUsage};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_derive::{Deserialize, Serialize};
+use std::{fs, path::PathBuf};
+
+/// Progress of `prove_by_block`, saved after each chunk and batch.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BlockProveCheckpoint {
+    /// Block range of the run, as resolved when the run started.
+    pub begin_block: i64,
+    pub end_block: i64,
+    /// Next block to fetch.
+    pub next_block: i64,
+    /// First block of the batch being built.
+    pub batch_begin_block: i64,
+    /// Traces held by `ChunkBuilder` which are not part of any chunk yet.
+    pub pending_traces: Vec<BlockTrace>,
+    pub acc_row_usage_normalized: RowUsage,
+    /// Chunks held by `BatchBuilder` which are not part of any batch yet.
+    pub pending_chunks: Vec<ChunkInfo>,
+}
+
+/// Progress of `prove_by_batch`, saved after each chunk and batch.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BatchProveCheckpoint {
+    /// Batch being handled.
+    pub batch_id: i64,
+    /// Number of chunks of `batch_id` already handled.
+    pub chunks_done: usize,
+    pub chunk_proofs: Vec<ChunkProofV2>,
+    #[serde(default)]
+    pub batch_meta: BatchMeta,
+}
+
+impl BatchProveCheckpoint {
+    /// Checkpoint before handling any chunk of `batch_id`.
+    pub fn new(batch_id: i64) -> Self {
+        Self {
+            batch_id,
+            chunks_done: 0,
+            chunk_proofs: vec![],
+            batch_meta: BatchMeta::default(),
+        }
+    }
+}
+
+/// Saves and loads the progress of a run as a json file.
+/// A checkpointer with an empty directory is disabled and never touches the disk.
+pub struct Checkpointer {
+    path: Option<PathBuf>,
+}
+
+impl Checkpointer {
+    pub fn new(dir: &str, name: &str) -> Self {
+        if dir.is_empty() {
+            return Self { path: None };
+        }
+        fs::create_dir_all(dir)
+            .unwrap_or_else(|e| panic!("checkpoint: failed to create dir {dir}: {e}"));
+        Self {
+            path: Some(PathBuf::from(dir).join(format!("{name}.json"))),
+        }
+    }
+
+    pub fn load<T: DeserializeOwned>(&self) -> Option<T> {
+        let path = self.path.as_ref()?;
+        if !path.exists() {
+            return None;
+        }
+        let state = fs::read(path)
+            .map_err(anyhow::Error::from)
+            .and_then(|bytes| Ok(serde_json::from_slice(&bytes)?))
+            .unwrap_or_else(|e| panic!("checkpoint: failed to load {}: {e}", path.display()));
+        log::info!("checkpoint: resuming from {}", path.display());
+        Some(state)
+    }
+
+    pub fn save<T: Serialize>(&self, state: &T) {
+        let Some(path) = self.path.as_ref() else {
+            return;
+        };
+        match Self::write(path, state) {
+            Ok(()) => log::debug!("checkpoint: saved {}", path.display()),
+            Err(e) => log::error!("checkpoint: failed to save {}: {e}", path.display()),
+        }
+    }
+
+    /// Removes the checkpoint once the run is finished.
+    pub fn clear(&self) {
+        if let Some(path) = self.path.as_ref() {
+            if path.exists() {
+                if let Err(e) = fs::remove_file(path) {
+                    log::error!("checkpoint: failed to remove {}: {e}", path.display());
+                }
+            }
+        }
+    }
+
+    // Write to a temp file first so a crash while saving never leaves a truncated checkpoint.
+    fn write<T: Serialize>(path: &PathBuf, state: &T) -> Result<()> {
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, serde_json::to_vec(state)?)?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+}
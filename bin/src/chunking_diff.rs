@@ -0,0 +1,258 @@
+use crate::{
+    chain_source::ChainSource,
+    failure_ledger::{FailedItem, FailureLedger},
+    prefetch::{PrefetchConfig, TracePrefetcher},
+    rollupscan_client,
+    run_report::{self, DivergenceRecord, ReportRecord},
+};
+use integration::proposer::{
+    chunk_info_from_traces, first_batch_cut, first_chunk_cut, BatchLimits, ChunkLimits,
+};
+use prover::{eth_types::l2_types::BlockTrace, ChunkInfo};
+use std::{fmt::Debug, sync::Arc};
+
+/// A chunk of the chain, with what is needed to check its boundaries.
+struct ChainChunk {
+    batch_id: i64,
+    chunk_id: i64,
+    end_block: i64,
+    traces: Vec<BlockTrace>,
+    info: ChunkInfo,
+}
+
+#[derive(Debug, Default)]
+struct Tally {
+    compared: usize,
+    diverged: usize,
+    // Not decided for lack of the following block or chunk.
+    undecided: usize,
+}
+
+/// Rebuilds the chunks and batches of `begin_batch..=end_batch` locally, and compares their
+/// boundaries with the chain's.
+///
+/// Each chain chunk (batch) is replayed by a new `ChunkBuilder` (`BatchBuilder`) with the block
+/// (chunk) following it, so that a divergence doesn't shift the following comparisons. The local
+/// one matches if it is cut right at the chain's boundary. Otherwise the divergence is logged and
+/// recorded in the run report, with the condition deciding the local boundary.
+pub async fn diff_chunking(
+    source: &Arc<ChainSource>,
+    begin_batch: i64,
+    end_batch: i64,
+    prefetch_config: PrefetchConfig,
+    ledger: &FailureLedger,
+    chunk_limits: &ChunkLimits,
+    batch_limits: &BatchLimits,
+) {
+    // The first chunk of the batch after the range decides the boundaries of the last batch.
+    let mut chain_chunks: Vec<(i64, rollupscan_client::ChunkInfo)> = vec![];
+    let mut end_batch = end_batch;
+    for batch_id in begin_batch..=end_batch + 1 {
+        let chunks = match source.get_chunk_info_by_batch_index(batch_id).await {
+            Ok(Some(chunks)) if !chunks.is_empty() => chunks,
+            Ok(_) if batch_id > end_batch => break,
+            Ok(_) => {
+                log::warn!("chunking_diff: no chunks in batch-{batch_id}, stopping there");
+                end_batch = batch_id - 1;
+                break;
+            }
+            Err(_) if batch_id > end_batch => break,
+            Err(e) => {
+                ledger.fail(
+                    FailedItem::Batch { batch_id },
+                    e.context(format!(
+                        "chunking_diff: failed to request rollupscan chunks API for batch-{batch_id}"
+                    )),
+                );
+                end_batch = batch_id - 1;
+                break;
+            }
+        };
+        let take = if batch_id > end_batch {
+            1
+        } else {
+            chunks.len()
+        };
+        chain_chunks.extend(chunks.into_iter().take(take).map(|c| (batch_id, c)));
+    }
+
+    let mut prefetcher = TracePrefetcher::new(source.clone(), prefetch_config);
+    prefetcher.push_blocks(
+        chain_chunks
+            .iter()
+            .flat_map(|(_, chunk)| chunk.start_block_number..=chunk.end_block_number),
+    );
+    let (mut chunk_tally, mut batch_tally) = (Tally::default(), Tally::default());
+    // The last loaded chunk, compared once the next one is loaded.
+    let mut prev: Option<ChainChunk> = None;
+    // Chunks of the batch of `prev`, before it, without traces.
+    let mut batch_chunks: Vec<ChainChunk> = vec![];
+    'chunk: for (batch_id, chunk) in chain_chunks {
+        let mut traces = vec![];
+        for _ in chunk.start_block_number..=chunk.end_block_number {
+            let (block_num, trace) = prefetcher.next().await.unwrap();
+            match trace {
+                Ok(trace) => traces.push(trace),
+                Err(e) => {
+                    // Boundaries can't be replayed across a missing block.
+                    ledger.fail(
+                        FailedItem::Block { block_num },
+                        e.context(format!("chunking_diff: failed to request l2geth block-trace API for block-{block_num}")),
+                    );
+                    break 'chunk;
+                }
+            }
+        }
+        let cur = ChainChunk {
+            batch_id,
+            chunk_id: chunk.index,
+            end_block: chunk.end_block_number,
+            info: chunk_info_from_traces(&traces),
+            traces,
+        };
+
+        if let Some(prev) = prev.take() {
+            compare_chunk(&prev, Some(&cur), chunk_limits, &mut chunk_tally);
+            let prev_batch_id = prev.batch_id;
+            // Traces are only needed for comparing chunks.
+            batch_chunks.push(ChainChunk {
+                traces: vec![],
+                ..prev
+            });
+            if cur.batch_id != prev_batch_id {
+                compare_batch(&batch_chunks, Some(&cur), batch_limits, &mut batch_tally);
+                batch_chunks.clear();
+            }
+        }
+        prev = Some(cur);
+    }
+    // Without the following chunk loaded, the last chunk of the range is compared alone.
+    if let Some(prev) = prev.filter(|prev| prev.batch_id <= end_batch) {
+        compare_chunk(&prev, None, chunk_limits, &mut chunk_tally);
+        batch_chunks.push(prev);
+        compare_batch(&batch_chunks, None, batch_limits, &mut batch_tally);
+    }
+
+    prefetcher.log_stats("chunking_diff");
+    log::info!(
+        "chunking_diff: batch-{begin_batch} to batch-{end_batch}: chunks {chunk_tally:?}, batches {batch_tally:?}"
+    );
+}
+
+fn compare_chunk(
+    chunk: &ChainChunk,
+    next: Option<&ChainChunk>,
+    limits: &ChunkLimits,
+    tally: &mut Tally,
+) {
+    let next_trace = next.map(|next| &next.traces[0]);
+    let local_cut = first_chunk_cut(limits, &chunk.traces, next_trace);
+    let block_num = |idx: usize| {
+        chunk
+            .traces
+            .get(idx)
+            .or(next_trace)
+            .and_then(|trace| trace.header.number)
+            .map_or(0, |n| n.as_u64() as i64)
+    };
+    let divergence = DivergenceRecord {
+        level: "chunk".to_string(),
+        batch_id: chunk.batch_id,
+        chunk_id: Some(chunk.chunk_id),
+        chain_end_block: chunk.end_block,
+        ..Default::default()
+    };
+    compare(
+        divergence,
+        chunk.traces.len(),
+        local_cut,
+        next.is_some(),
+        block_num,
+        tally,
+    );
+}
+
+fn compare_batch(
+    chunks: &[ChainChunk],
+    next: Option<&ChainChunk>,
+    limits: &BatchLimits,
+    tally: &mut Tally,
+) {
+    let infos: Vec<ChunkInfo> = chunks.iter().map(|chunk| chunk.info.clone()).collect();
+    let local_cut = first_batch_cut(limits, &infos, next.map(|next| &next.info));
+    let block_num = |idx: usize| chunks.get(idx).or(next).map_or(0, |chunk| chunk.end_block);
+    let last = chunks.last().unwrap();
+    let divergence = DivergenceRecord {
+        level: "batch".to_string(),
+        batch_id: last.batch_id,
+        chain_end_block: last.end_block,
+        ..Default::default()
+    };
+    compare(
+        divergence,
+        chunks.len(),
+        local_cut,
+        next.is_some(),
+        block_num,
+        tally,
+    );
+}
+
+/// Compares the chain's `len` items (blocks or chunks) with the local cut of them.
+/// `end_block(idx)` is the end block of the idx-th item, where the `len`-th is the following one.
+fn compare<C: Debug>(
+    mut divergence: DivergenceRecord,
+    len: usize,
+    local_cut: Option<(usize, C)>,
+    has_next: bool,
+    end_block: impl Fn(usize) -> i64,
+    tally: &mut Tally,
+) {
+    let id = format!(
+        "chunking_diff: {} of batch-{}{}",
+        divergence.level,
+        divergence.batch_id,
+        divergence
+            .chunk_id
+            .map_or(String::new(), |id| format!(" chunk-{id}")),
+    );
+    match local_cut {
+        Some((local_len, cut)) if local_len == len => {
+            log::debug!("{id}: matched, cut by {cut:?}");
+            tally.compared += 1;
+            return;
+        }
+        None if !has_next => {
+            log::info!("{id}: undecided without the following {}", divergence.level);
+            tally.undecided += 1;
+            return;
+        }
+        Some((local_len, cut)) if local_len < len => {
+            // The local one ends early, as decided by the condition.
+            divergence.block_num = end_block(local_len - 1);
+            divergence.local_end_block = Some(divergence.block_num);
+            divergence.condition = Some(format!("{cut:?}"));
+        }
+        Some((local_len, cut)) => {
+            // The local one goes on, and ends by the condition with the following item.
+            divergence.block_num = divergence.chain_end_block;
+            divergence.local_end_block = Some(end_block(local_len - 1));
+            log::debug!("{id}: local one ends at next by {cut:?}");
+        }
+        None => {
+            divergence.block_num = divergence.chain_end_block;
+        }
+    }
+    tally.compared += 1;
+    tally.diverged += 1;
+    log::warn!(
+        "{id}: diverged at block-{}, chain ends at block-{}, local ends at {}, deciding condition {}",
+        divergence.block_num,
+        divergence.chain_end_block,
+        divergence
+            .local_end_block
+            .map_or("none".to_string(), |n| format!("block-{n}")),
+        divergence.condition.as_deref().unwrap_or("none on local side"),
+    );
+    run_report::record(&ReportRecord::Divergence(divergence));
+}
@@ -0,0 +1,189 @@
+use anyhow::Result;
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::PathBuf,
+    str::FromStr,
+    sync::Mutex,
+};
+
+/// What to do with a block or batch whose RPC requests still fail after retries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailurePolicy {
+    Abort,
+    Skip,
+}
+
+impl FromStr for FailurePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "abort" => Ok(Self::Abort),
+            "skip" => Ok(Self::Skip),
+            _ => Err(format!("unknown failure policy {s}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum FailedItem {
+    Block {
+        block_num: i64,
+    },
+    /// Blocks dropped together, e.g. the chunk of a failed block.
+    BlockRange {
+        begin_block: i64,
+        end_block: i64,
+    },
+    Batch {
+        batch_id: i64,
+    },
+}
+
+impl FailedItem {
+    /// The blocks `begin_block..=end_block`, as a `Block` if only one.
+    pub fn blocks(begin_block: i64, end_block: i64) -> Self {
+        if begin_block == end_block {
+            Self::Block {
+                block_num: begin_block,
+            }
+        } else {
+            Self::BlockRange {
+                begin_block,
+                end_block,
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FailureRecord {
+    #[serde(flatten)]
+    pub item: FailedItem,
+    pub error: String,
+}
+
+/// Records failed items as json lines, so that a later run could re-process them.
+/// Without a path, failures are only logged.
+pub struct FailureLedger {
+    path: Option<PathBuf>,
+    policy: FailurePolicy,
+    retry: Mutex<Option<Retry>>,
+}
+
+// Recorded failures being re-processed.
+struct Retry {
+    // Not re-processed yet.
+    pending: VecDeque<FailureRecord>,
+    // Failed again.
+    failed: Vec<FailureRecord>,
+}
+
+impl FailureLedger {
+    pub fn new(path: &str, policy: FailurePolicy) -> Self {
+        Self {
+            path: (!path.is_empty()).then(|| PathBuf::from(path)),
+            policy,
+            retry: Mutex::new(None),
+        }
+    }
+
+    /// Records the failure, then aborts the run, or returns to let the caller skip the item,
+    /// according to the policy.
+    pub fn fail(&self, item: FailedItem, err: anyhow::Error) {
+        let record = FailureRecord {
+            item,
+            error: format!("{err:#}"),
+        };
+        let mut retry = self.retry.lock().unwrap();
+        if let Some(retry) = retry.as_mut() {
+            // The ledger is rewritten once the retry is over.
+            retry.failed.push(record.clone());
+            if self.policy == FailurePolicy::Abort {
+                let records: Vec<_> = retry.failed.iter().chain(&retry.pending).cloned().collect();
+                if let Err(e) = self.rewrite(&records) {
+                    log::error!("failure ledger: failed to rewrite: {e}");
+                }
+            }
+        } else if let Err(e) = self.append(&record) {
+            log::error!("failure ledger: failed to record {item:?}: {e}");
+        }
+        drop(retry);
+        match self.policy {
+            FailurePolicy::Abort => panic!("{}", record.error),
+            FailurePolicy::Skip => log::error!("skip {item:?}: {}", record.error),
+        }
+    }
+
+    /// Starts re-processing the recorded failures, see `next_retry`. The ledger keeps them until
+    /// `finish_retry`, or until the run aborts, keeping the items failing again and the ones
+    /// not re-processed yet.
+    pub fn start_retry(&self) -> Result<usize> {
+        let records = self.read()?;
+        let num_records = records.len();
+        *self.retry.lock().unwrap() = Some(Retry {
+            pending: records.into(),
+            failed: vec![],
+        });
+        Ok(num_records)
+    }
+
+    /// The next recorded failure to re-process.
+    pub fn next_retry(&self) -> Option<FailureRecord> {
+        self.retry.lock().unwrap().as_mut()?.pending.pop_front()
+    }
+
+    /// Rewrites the ledger with the items failing again.
+    pub fn finish_retry(&self) -> Result<()> {
+        let Some(retry) = self.retry.lock().unwrap().take() else {
+            return Ok(());
+        };
+        let records: Vec<_> = retry.failed.into_iter().chain(retry.pending).collect();
+        self.rewrite(&records)
+    }
+
+    fn read(&self) -> Result<Vec<FailureRecord>> {
+        let Some(path) = self.path.as_ref() else {
+            return Ok(vec![]);
+        };
+        if !path.exists() {
+            return Ok(vec![]);
+        }
+        let records = fs::read_to_string(path)?
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(serde_json::from_str)
+            .collect::<Result<Vec<FailureRecord>, _>>()?;
+        Ok(records)
+    }
+
+    // Replaces the ledger with the records, through a temp file so that the records are not lost
+    // to an interrupted write.
+    fn rewrite(&self, records: &[FailureRecord]) -> Result<()> {
+        let Some(path) = self.path.as_ref() else {
+            return Ok(());
+        };
+        let mut lines = String::new();
+        for record in records {
+            lines += &serde_json::to_string(record)?;
+            lines.push('\n');
+        }
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, lines)?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    fn append(&self, record: &FailureRecord) -> Result<()> {
+        let Some(path) = self.path.as_ref() else {
+            return Ok(());
+        };
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(file, "{}", serde_json::to_string(record)?)?;
+        Ok(())
+    }
+}
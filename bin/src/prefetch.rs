@@ -0,0 +1,164 @@
+use crate::chain_source::ChainSource;
+use anyhow::{anyhow, Result};
+use prover::eth_types::l2_types::BlockTrace;
+use std::{
+    collections::VecDeque,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{
+    sync::{oneshot, Semaphore},
+    task::JoinHandle,
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct PrefetchConfig {
+    /// Max number of traces fetched ahead of the consumer.
+    pub window: usize,
+    /// Max number of requests in flight at the same time.
+    pub concurrency: usize,
+    /// Max number of blocks in one request, sent as a JSON-RPC batch if more than 1.
+    pub batch_size: usize,
+}
+
+/// Fetches block traces ahead of the consumer, and hands them out in block order.
+pub struct TracePrefetcher {
+    source: Arc<ChainSource>,
+    config: PrefetchConfig,
+    semaphore: Arc<Semaphore>,
+    // Blocks not requested yet.
+    queued: VecDeque<i64>,
+    // Blocks requested but not consumed yet, in block order.
+    in_flight: VecDeque<(i64, oneshot::Receiver<Result<BlockTrace>>)>,
+    // Requests of the blocks in flight in block order, each with its number of blocks not
+    // consumed yet.
+    requests: VecDeque<(JoinHandle<()>, usize)>,
+    started: Instant,
+    fetch_wait: Duration,
+}
+
+impl TracePrefetcher {
+    pub fn new(source: Arc<ChainSource>, config: PrefetchConfig) -> Self {
+        let config = PrefetchConfig {
+            window: config.window.max(1),
+            concurrency: config.concurrency.max(1),
+            batch_size: config.batch_size.max(1),
+        };
+        Self {
+            source,
+            config,
+            semaphore: Arc::new(Semaphore::new(config.concurrency)),
+            queued: VecDeque::new(),
+            in_flight: VecDeque::new(),
+            requests: VecDeque::new(),
+            started: Instant::now(),
+            fetch_wait: Duration::ZERO,
+        }
+    }
+
+    /// Appends blocks to fetch after the already pushed ones.
+    pub fn push_blocks(&mut self, blocks: impl IntoIterator<Item = i64>) {
+        self.queued.extend(blocks);
+        self.fill_window();
+    }
+
+    /// Returns the trace of the next pushed block, or `None` if all pushed blocks are consumed.
+    pub async fn next(&mut self) -> Option<(i64, Result<BlockTrace>)> {
+        let (block_num, receiver) = self.pop_in_flight(false)?;
+        self.fill_window();
+
+        let wait_start = Instant::now();
+        let trace = receiver
+            .await
+            .map_err(|_| anyhow!("fetching task of block-{block_num} failed"))
+            .and_then(|trace| trace);
+        self.fetch_wait += wait_start.elapsed();
+
+        Some((block_num, trace))
+    }
+
+    /// Drops the next `n` pushed blocks without waiting for their traces.
+    pub fn discard(&mut self, n: usize) {
+        for _ in 0..n {
+            if self.pop_in_flight(true).is_none() && self.queued.pop_front().is_none() {
+                break;
+            }
+        }
+        self.fill_window();
+    }
+
+    /// Logs how much time of the run was spent on waiting for traces vs. computing.
+    pub fn log_stats(&self, id: &str) {
+        let total = self.started.elapsed();
+        let compute = total.saturating_sub(self.fetch_wait);
+        log::info!(
+            "{id}: total {:?}, waiting on fetch {:?} ({:.1}%), computing {:?} ({:.1}%)",
+            total,
+            self.fetch_wait,
+            100.0 * self.fetch_wait.as_secs_f64() / total.as_secs_f64(),
+            compute,
+            100.0 * compute.as_secs_f64() / total.as_secs_f64(),
+        );
+    }
+
+    // Pops the next block in flight, dropping its request once all blocks of the request are
+    // popped. The request is aborted if `abort`, i.e. none of its traces are waited for.
+    fn pop_in_flight(
+        &mut self,
+        abort: bool,
+    ) -> Option<(i64, oneshot::Receiver<Result<BlockTrace>>)> {
+        let block = self.in_flight.pop_front()?;
+        let (request, remaining) = self.requests.front_mut().unwrap();
+        *remaining -= 1;
+        if *remaining == 0 {
+            if abort {
+                request.abort();
+            }
+            self.requests.pop_front();
+        }
+        Some(block)
+    }
+
+    fn fill_window(&mut self) {
+        while self.in_flight.len() < self.config.window && !self.queued.is_empty() {
+            let n = (self.config.window - self.in_flight.len())
+                .min(self.config.batch_size)
+                .min(self.queued.len());
+            let block_nums: Vec<i64> = self.queued.drain(..n).collect();
+            let mut senders = Vec::with_capacity(n);
+            for &block_num in &block_nums {
+                let (sender, receiver) = oneshot::channel();
+                senders.push(sender);
+                self.in_flight.push_back((block_num, receiver));
+            }
+            let source = self.source.clone();
+            let semaphore = self.semaphore.clone();
+            let request = tokio::spawn(async move {
+                let traces = match semaphore.acquire_owned().await {
+                    Ok(_permit) if block_nums.len() == 1 => vec![(
+                        block_nums[0],
+                        source.get_block_trace_by_num(block_nums[0]).await,
+                    )],
+                    Ok(_permit) => source.get_block_traces_by_nums(&block_nums).await,
+                    Err(e) => block_nums
+                        .iter()
+                        .map(|&block_num| (block_num, Err(anyhow!("{e}"))))
+                        .collect(),
+                };
+                for (sender, (_, trace)) in senders.into_iter().zip(traces) {
+                    // The block may be discarded already.
+                    let _ = sender.send(trace);
+                }
+            });
+            self.requests.push_back((request, n));
+        }
+    }
+}
+
+impl Drop for TracePrefetcher {
+    fn drop(&mut self) {
+        for (request, _) in &self.requests {
+            request.abort();
+        }
+    }
+}
@@ -0,0 +1,271 @@
+use crate::{
+    chain_source::ChainSource,
+    failure_ledger::{FailedItem, FailureLedger},
+    prefetch::{PrefetchConfig, TracePrefetcher},
+    run_report::{self, ReportRecord, SimulationRecord},
+};
+use integration::{
+    constants::N_BLOB_BYTES,
+    proposer::{
+        blob_size, chunk_info_from_traces, BatchBuilder, BatchLimits, ChunkBuilder, ChunkLimits,
+        ProposedBatch, ProposedChunk,
+    },
+};
+use itertools::{iproduct, Itertools};
+use std::{env, str::FromStr, sync::Arc};
+
+/// Values of the swept limits. A limit without values keeps its configured value.
+#[derive(Debug, Default)]
+pub struct SweepSpec {
+    pub max_blocks: Vec<Option<usize>>,
+    pub max_rows: Vec<Option<usize>>,
+    pub max_chunks: Vec<usize>,
+    pub max_blob_bytes: Vec<usize>,
+}
+
+impl SweepSpec {
+    /// Reads comma separated values, e.g. `SWEEP_MAX_BLOCKS=50,100,none`.
+    pub fn from_env() -> Self {
+        Self {
+            max_blocks: parse_optional_list("SWEEP_MAX_BLOCKS"),
+            max_rows: parse_optional_list("SWEEP_MAX_ROWS"),
+            max_chunks: parse_list("SWEEP_MAX_CHUNKS"),
+            max_blob_bytes: parse_list("SWEEP_MAX_BLOB_BYTES"),
+        }
+    }
+
+    /// All combinations of the swept values, on top of the configured limits.
+    pub fn grid(
+        &self,
+        chunk_limits: &ChunkLimits,
+        batch_limits: &BatchLimits,
+    ) -> Vec<(ChunkLimits, BatchLimits)> {
+        iproduct!(
+            or_base(&self.max_blocks, chunk_limits.max_blocks),
+            or_base(&self.max_rows, chunk_limits.max_rows),
+            or_base(&self.max_chunks, batch_limits.max_chunks),
+            or_base(&self.max_blob_bytes, batch_limits.max_blob_bytes)
+        )
+        .map(|(max_blocks, max_rows, max_chunks, max_blob_bytes)| {
+            (
+                ChunkLimits {
+                    max_blocks,
+                    max_rows,
+                    ..chunk_limits.clone()
+                },
+                BatchLimits {
+                    max_chunks,
+                    max_blob_bytes,
+                    ..batch_limits.clone()
+                },
+            )
+        })
+        .collect()
+    }
+}
+
+fn or_base<T: Clone>(values: &[T], base: T) -> Vec<T> {
+    if values.is_empty() {
+        vec![base]
+    } else {
+        values.to_vec()
+    }
+}
+
+fn parse_list<T: FromStr>(name: &str) -> Vec<T> {
+    env::var(name)
+        .unwrap_or_default()
+        .split(',')
+        .filter(|s| !s.is_empty())
+        .map(|s| {
+            s.trim()
+                .parse()
+                .unwrap_or_else(|_| panic!("invalid value {s} of {name}"))
+        })
+        .collect()
+}
+
+// "none" means unlimited.
+fn parse_optional_list<T: FromStr>(name: &str) -> Vec<Option<T>> {
+    parse_list::<String>(name)
+        .into_iter()
+        .map(|s| {
+            (s != "none").then(|| {
+                s.parse()
+                    .unwrap_or_else(|_| panic!("invalid value {s} of {name}"))
+            })
+        })
+        .collect()
+}
+
+/// Chunking and batching of the block range under one parameter set.
+struct Simulation {
+    chunk_limits: ChunkLimits,
+    batch_limits: BatchLimits,
+    chunk_builder: ChunkBuilder,
+    batch_builder: BatchBuilder,
+    num_chunks: usize,
+    num_batches: usize,
+    gas: u64,
+    blob_utilizations: Vec<f64>,
+}
+
+impl Simulation {
+    fn new(chunk_limits: ChunkLimits, batch_limits: BatchLimits) -> Self {
+        Self {
+            chunk_builder: ChunkBuilder::new(chunk_limits.clone()),
+            batch_builder: BatchBuilder::new(batch_limits.clone()),
+            chunk_limits,
+            batch_limits,
+            num_chunks: 0,
+            num_batches: 0,
+            gas: 0,
+            blob_utilizations: vec![],
+        }
+    }
+
+    fn add_chunk(&mut self, chunk: ProposedChunk) {
+        self.num_chunks += 1;
+        self.gas += chunk
+            .traces
+            .iter()
+            .map(|trace| trace.header.gas_used.as_u64())
+            .sum::<u64>();
+        if let Some(batch) = self
+            .batch_builder
+            .add(chunk_info_from_traces(&chunk.traces))
+        {
+            self.add_batch(batch);
+        }
+    }
+
+    fn add_batch(&mut self, batch: ProposedBatch) {
+        self.num_batches += 1;
+        // A single chunk the codec can't encode makes a batch by itself, with no blob.
+        if let Ok(size) = blob_size(self.batch_limits.codec, &batch.chunks) {
+            self.blob_utilizations
+                .push(size as f64 / N_BLOB_BYTES as f64);
+        }
+    }
+
+    /// Ends the range, taking the pending blocks and chunks as the last chunk and batch.
+    fn finish(mut self) -> SimulationRecord {
+        if let Some(chunk) = self.chunk_builder.finish() {
+            self.add_chunk(chunk);
+        }
+        if let Some(batch) = self.batch_builder.finish() {
+            self.add_batch(batch);
+        }
+
+        let utilizations = self
+            .blob_utilizations
+            .iter()
+            .copied()
+            .sorted_by(f64::total_cmp)
+            .collect_vec();
+        let percentile = |p: usize| {
+            (!utilizations.is_empty()).then(|| utilizations[(utilizations.len() - 1) * p / 100])
+        };
+        SimulationRecord {
+            max_blocks: self.chunk_limits.max_blocks,
+            max_rows: self.chunk_limits.max_rows,
+            max_chunks: self.batch_limits.max_chunks,
+            max_blob_bytes: self.batch_limits.max_blob_bytes,
+            num_chunks: self.num_chunks,
+            num_batches: self.num_batches,
+            // A proof for each chunk and each batch.
+            num_proofs: self.num_chunks + self.num_batches,
+            avg_gas_per_chunk: self.gas.checked_div(self.num_chunks as u64).unwrap_or(0),
+            blob_utilization_min: utilizations.first().copied(),
+            blob_utilization_p50: percentile(50),
+            blob_utilization_p90: percentile(90),
+            blob_utilization_max: utilizations.last().copied(),
+        }
+    }
+}
+
+/// Replays the traces of the block range through the chunk and batch builders under each
+/// parameter set of the grid, without proving. One pass over the traces feeds all parameter
+/// sets, so each block is fetched once but checked by the CCC once per parameter set.
+pub async fn simulate(
+    source: &Arc<ChainSource>,
+    begin_block: i64,
+    end_block: i64,
+    prefetch_config: PrefetchConfig,
+    ledger: &FailureLedger,
+    grid: Vec<(ChunkLimits, BatchLimits)>,
+) {
+    let (begin_block, end_block) = if begin_block == 0 && end_block == 0 {
+        source.get_default_block_range().await.unwrap()
+    } else {
+        (begin_block, end_block)
+    };
+    log::info!(
+        "proposer_sim: simulating {} parameter sets over block-{begin_block} to block-{end_block}",
+        grid.len()
+    );
+    let mut simulations = grid
+        .into_iter()
+        .map(|(chunk_limits, batch_limits)| Simulation::new(chunk_limits, batch_limits))
+        .collect_vec();
+
+    let mut prefetcher = TracePrefetcher::new(source.clone(), prefetch_config);
+    prefetcher.push_blocks(begin_block..=end_block);
+    while let Some((block_num, trace)) = prefetcher.next().await {
+        let trace = match trace {
+            Ok(trace) => trace,
+            Err(e) => {
+                ledger.fail(
+                    FailedItem::Block { block_num },
+                    e.context(format!(
+                        "proposer_sim: failed to request l2geth block-trace API for block-{block_num}"
+                    )),
+                );
+                continue;
+            }
+        };
+        for simulation in &mut simulations {
+            if let Some(chunk) = simulation.chunk_builder.add(trace.clone()) {
+                simulation.add_chunk(chunk);
+            }
+        }
+    }
+    prefetcher.log_stats("proposer_sim");
+
+    let fmt_opt = |v: Option<usize>| v.map_or("none".to_string(), |v| v.to_string());
+    let fmt_ratio = |v: Option<f64>| v.map_or("-".to_string(), |v| format!("{v:.3}"));
+    log::info!(
+        "proposer_sim: {:>10} {:>10} {:>10} {:>14} | {:>8} {:>8} {:>8} {:>12} | {:>6} {:>6} {:>6} {:>6}",
+        "max_blocks",
+        "max_rows",
+        "max_chunks",
+        "max_blob_bytes",
+        "chunks",
+        "batches",
+        "proofs",
+        "gas/chunk",
+        "blob",
+        "p50",
+        "p90",
+        "max",
+    );
+    for simulation in simulations {
+        let record = simulation.finish();
+        log::info!(
+            "proposer_sim: {:>10} {:>10} {:>10} {:>14} | {:>8} {:>8} {:>8} {:>12} | {:>6} {:>6} {:>6} {:>6}",
+            fmt_opt(record.max_blocks),
+            fmt_opt(record.max_rows),
+            record.max_chunks,
+            record.max_blob_bytes,
+            record.num_chunks,
+            record.num_batches,
+            record.num_proofs,
+            record.avg_gas_per_chunk,
+            fmt_ratio(record.blob_utilization_min),
+            fmt_ratio(record.blob_utilization_p50),
+            fmt_ratio(record.blob_utilization_p90),
+            fmt_ratio(record.blob_utilization_max),
+        );
+        run_report::record(&ReportRecord::Simulation(record));
+    }
+}
@@ -1,56 +1,102 @@
 use prover::{eth_types::l2_types::BlockTrace, ChunkProofV2};
-use std::panic::{catch_unwind, AssertUnwindSafe};
-
-#[cfg(feature = "batch-prove")]
-use prover::{BatchHeader, MAX_AGG_SNARKS};
-
-#[cfg(feature = "batch-prove")]
-pub fn prove_batch(
-    id: &str,
-    chunk_proofs: Vec<ChunkProofV2>,
-    batch_header: BatchHeader<MAX_AGG_SNARKS>,
-) {
-    use integration::prove::get_blob_from_chunks;
-    use itertools::Itertools;
-    use prover::BatchProvingTask;
-
-    let chunk_infos = chunk_proofs
-        .iter()
-        .map(|p| p.inner.chunk_info().clone())
-        .collect_vec();
-    let blob_bytes = get_blob_from_chunks(&chunk_infos);
-    let batch = BatchProvingTask {
-        chunk_proofs,
-        batch_header,
-        blob_bytes,
-    };
+use std::{
+    any::Any,
+    panic::{catch_unwind, AssertUnwindSafe},
+};
+
+#[cfg(feature = "chunk-prove")]
+use halo2_proofs::{halo2curves::bn256::Bn256, poly::kzg::commitment::ParamsKZG};
+#[cfg(feature = "chunk-prove")]
+use prover::{ChunkProver, ChunkVerifier};
+#[cfg(feature = "chunk-prove")]
+use std::{
+    collections::BTreeMap,
+    sync::{Mutex, OnceLock},
+};
+
+#[cfg(all(feature = "batch-prove", not(feature = "bundle-prove")))]
+pub fn prove_batch(id: &str, batch: prover::BatchProvingTask) -> Result<(), String> {
     let result = catch_unwind(AssertUnwindSafe(|| prover::batch_prove(id, batch)));
 
     match result {
-        Ok(_) => log::info!("{id}: succeeded to prove batch"),
+        Ok(_) => {
+            log::info!("{id}: succeeded to prove batch");
+            Ok(())
+        }
         Err(err) => {
-            let panic_err = if let Some(s) = err.downcast_ref::<String>() {
-                s.to_string()
-            } else if let Some(s) = err.downcast_ref::<&str>() {
-                s.to_string()
-            } else {
-                format!("unable to get panic info {err:?}")
-            };
+            let panic_err = panic_message(err);
             log::error!("{id}: failed to prove batch:\n{panic_err:?}");
+            Err(panic_err)
         }
     }
 }
 
-pub fn prove_chunk(id: &str, traces: Vec<BlockTrace>) -> Option<ChunkProofV2> {
+/// Provers of concurrent chunk proving workers, see `init_chunk_prover_pool`.
+#[cfg(feature = "chunk-prove")]
+static CHUNK_PROVER_POOL: OnceLock<ChunkProverPool> = OnceLock::new();
+
+/// Chunk provers sharing one params map, each used by one worker at a time.
+#[cfg(feature = "chunk-prove")]
+struct ChunkProverPool {
+    params_map: &'static BTreeMap<u32, ParamsKZG<Bn256>>,
+    assets_dir: String,
+    // Idle provers. A worker finding none constructs a new one.
+    provers: Mutex<Vec<ChunkProver<'static>>>,
+    verifier: ChunkVerifier<'static>,
+}
+
+#[cfg(feature = "chunk-prove")]
+impl ChunkProverPool {
+    fn prove(&self, id: &str, task: prover::ChunkProvingTask) -> ChunkProofV2 {
+        log::info!("{id}: chunk-prove BEGIN");
+        let idle = self.provers.lock().unwrap().pop();
+        let mut prover = idle.unwrap_or_else(|| {
+            log::info!("{id}: constructing a new chunk prover");
+            ChunkProver::from_params_and_assets(self.params_map, &self.assets_dir)
+        });
+        let proof = prover
+            .gen_halo2_chunk_proof(task, None, None, None)
+            .unwrap_or_else(|e| panic!("{id}: failed to generate chunk proof: {e}"));
+        self.provers.lock().unwrap().push(prover);
+
+        self.verifier
+            .verify_chunk_proof(&proof)
+            .unwrap_or_else(|e| panic!("{id}: failed to verify chunk proof: {e}"));
+        log::info!("{id}: chunk-prove END");
+        proof
+    }
+}
+
+/// Proves chunks with a pool of provers instead of the single global one, so that several
+/// chunks could be proven at the same time. Each prover needs the memory of a whole proving
+/// pipeline, so callers should limit the number of concurrent `prove_chunk`.
+#[cfg(feature = "chunk-prove")]
+pub fn init_chunk_prover_pool(params_dir: &str, assets_dir: &str) {
+    CHUNK_PROVER_POOL.get_or_init(|| {
+        let params_map: &'static _ = Box::leak(Box::new(prover::Prover::load_params_map(
+            params_dir,
+            &prover::CHUNK_PROVER_DEGREES,
+        )));
+        ChunkProverPool {
+            params_map,
+            assets_dir: assets_dir.to_string(),
+            provers: Mutex::new(vec![]),
+            verifier: ChunkVerifier::from_params_and_assets(params_map, assets_dir),
+        }
+    });
+}
+
+/// Proof is `None` if the chunk is only mock-proven or inner-proven.
+pub fn prove_chunk(id: &str, traces: Vec<BlockTrace>) -> Result<Option<ChunkProofV2>, String> {
     let result = catch_unwind(AssertUnwindSafe(|| {
         #[cfg(not(feature = "chunk-prove"))]
         let proof = None::<ChunkProofV2>;
 
         #[cfg(feature = "chunk-prove")]
-        let proof = Some(prover::chunk_prove(
-            id,
-            prover::ChunkProvingTask::new(traces),
-        ));
+        let proof = Some(match CHUNK_PROVER_POOL.get() {
+            Some(pool) => pool.prove(id, prover::ChunkProvingTask::new(traces)),
+            None => prover::chunk_prove(id, prover::ChunkProvingTask::new(traces)),
+        });
         #[cfg(not(any(feature = "inner-prove", feature = "chunk-prove")))]
         mock_prove(id, traces);
 
@@ -60,23 +106,28 @@ pub fn prove_chunk(id: &str, traces: Vec<BlockTrace>) -> Option<ChunkProofV2> {
     match result {
         Ok(proof) => {
             log::info!("{id}: succeeded to prove chunk");
-            proof
+            Ok(proof)
         }
         Err(err) => {
-            let panic_err = if let Some(s) = err.downcast_ref::<String>() {
-                s.to_string()
-            } else if let Some(s) = err.downcast_ref::<&str>() {
-                s.to_string()
-            } else {
-                format!("unable to get panic info {err:?}")
-            };
+            let panic_err = panic_message(err);
             log::error!("{id}: failed to prove chunk:\n{panic_err:?}");
 
-            None
+            Err(panic_err)
         }
     }
 }
 
+/// Message of a panic caught by `catch_unwind`.
+pub fn panic_message(err: Box<dyn Any + Send>) -> String {
+    if let Some(s) = err.downcast_ref::<String>() {
+        s.to_string()
+    } else if let Some(s) = err.downcast_ref::<&str>() {
+        s.to_string()
+    } else {
+        format!("unable to get panic info {err:?}")
+    }
+}
+
 #[cfg(not(any(feature = "inner-prove", feature = "chunk-prove")))]
 fn mock_prove(id: &str, traces: Vec<prover::eth_types::l2_types::BlockTrace>) {
     log::info!("{id}: mock-prove BEGIN");
@@ -0,0 +1,156 @@
+use crate::rollupscan_client::ChunkInfo;
+use anyhow::{anyhow, Result};
+use integration::{capacity_checker::split_block_trace_by_tx, trace_check::ensure_block_trace};
+use prover::{eth_types::l2_types::BlockTrace, get_block_trace_from_file};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Serves block traces and chunk layouts from a local trace tree, laid out as
+/// `batch_N/chunk_M/block_K.json` like `integration/tests/extra_traces`.
+pub struct Client {
+    id: String,
+    blocks: BTreeMap<i64, PathBuf>,
+    batches: BTreeMap<i64, Vec<ChunkInfo>>,
+}
+
+impl Client {
+    pub fn new(id: &str, trace_dir: &str) -> Result<Self> {
+        let mut blocks = BTreeMap::new();
+        let mut batches = BTreeMap::new();
+        for (batch_index, batch_dir) in numbered_entries(trace_dir, "batch_", true)? {
+            let mut chunks = vec![];
+            for (chunk_index, chunk_dir) in numbered_entries(&batch_dir, "chunk_", true)? {
+                let chunk_blocks = numbered_entries(&chunk_dir, "block_", false)?
+                    .into_iter()
+                    .filter(|(_, path)| {
+                        // Skip placeholders left by interrupted downloads.
+                        let is_empty = fs::metadata(path).map_or(true, |m| m.len() == 0);
+                        if is_empty {
+                            log::warn!("{id}: skip empty trace file {}", path.display());
+                        }
+                        !is_empty
+                    })
+                    .collect::<Vec<_>>();
+                let (Some((start, _)), Some((end, _))) =
+                    (chunk_blocks.first(), chunk_blocks.last())
+                else {
+                    log::warn!("{id}: no traces in {}", chunk_dir.display());
+                    continue;
+                };
+                chunks.push(ChunkInfo {
+                    index: chunk_index,
+                    start_block_number: *start,
+                    end_block_number: *end,
+                });
+                blocks.extend(chunk_blocks);
+            }
+            batches.insert(batch_index, chunks);
+        }
+        log::info!(
+            "{id}: loaded trace dir {trace_dir}, {} batches, {} blocks",
+            batches.len(),
+            blocks.len()
+        );
+
+        Ok(Self {
+            id: id.to_string(),
+            blocks,
+            batches,
+        })
+    }
+
+    pub fn get_block_number(&self) -> Result<u64> {
+        self.blocks
+            .keys()
+            .last()
+            .map(|n| *n as u64)
+            .ok_or_else(|| anyhow!("{}: no traces in trace dir", self.id))
+    }
+
+    /// The whole block range covered by the trace dir.
+    pub fn get_block_range(&self) -> Result<(i64, i64)> {
+        match (self.blocks.keys().next(), self.blocks.keys().last()) {
+            (Some(first), Some(last)) => Ok((*first, *last)),
+            _ => Err(anyhow!("{}: no traces in trace dir", self.id)),
+        }
+    }
+
+    pub fn get_block_trace_by_num(&self, block_num: i64) -> Result<BlockTrace> {
+        log::info!("{}: loading trace of block {}", self.id, block_num);
+
+        let path = self
+            .blocks
+            .get(&block_num)
+            .ok_or_else(|| anyhow!("{}: no trace of block {block_num}", self.id))?;
+        let trace = get_block_trace_from_file(path);
+        ensure_block_trace(&trace, block_num as u64, None)
+            .map_err(|e| e.context(format!("{}: {}", self.id, path.display())))?;
+        Ok(trace)
+    }
+
+    /// Tx-by-tx traces dumped into the `txbytx` dir of the chunk by `trace_dumper`, or else the
+    /// block trace split by tx.
+    pub fn get_txbytx_trace_by_num(&self, block_num: i64) -> Result<Vec<BlockTrace>> {
+        if let Some(path) = self.blocks.get(&block_num).map(|path| txbytx_path(path)) {
+            if fs::metadata(&path).map_or(false, |m| m.len() > 0) {
+                log::info!("{}: loading tx-by-tx trace of block {}", self.id, block_num);
+                return Ok(serde_json::from_slice(&fs::read(path)?)?);
+            }
+        }
+        let trace = self.get_block_trace_by_num(block_num)?;
+        Ok(split_block_trace_by_tx(&trace))
+    }
+
+    pub fn get_chunk_info_by_batch_index(
+        &self,
+        batch_index: i64,
+    ) -> Result<Option<Vec<ChunkInfo>>> {
+        let chunks = self.batches.get(&batch_index).cloned();
+        log::info!(
+            "{}: handling batch {}, chunk size {}",
+            self.id,
+            batch_index,
+            chunks.as_ref().map_or(0, |c| c.len())
+        );
+
+        Ok(chunks)
+    }
+}
+
+// `chunk_M/txbytx/block_K.json` of `chunk_M/block_K.json`.
+fn txbytx_path(block_path: &Path) -> PathBuf {
+    let file_name = block_path.file_name().unwrap();
+    block_path.parent().unwrap().join("txbytx").join(file_name)
+}
+
+/// Returns the entries of `dir` named `{prefix}N` (or `{prefix}N.json` for files), sorted by N.
+/// `prefix` is optional for files, e.g. `batch_5/chunk_5/6.json`.
+fn numbered_entries(
+    dir: impl AsRef<Path>,
+    prefix: &str,
+    is_dir: bool,
+) -> Result<Vec<(i64, PathBuf)>> {
+    let mut entries = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir() == is_dir)
+        .filter_map(|path| {
+            let name = if is_dir {
+                path.file_name()?.to_str()?.strip_prefix(prefix)?
+            } else {
+                if path.extension()? != "json" {
+                    return None;
+                }
+                let stem = path.file_stem()?.to_str()?;
+                stem.strip_prefix(prefix).unwrap_or(stem)
+            };
+            let num = name.parse::<i64>().ok()?;
+            Some((num, path))
+        })
+        .collect::<Vec<_>>();
+    entries.sort_by_key(|(num, _)| *num);
+    Ok(entries)
+}
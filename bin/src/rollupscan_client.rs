@@ -1,8 +1,12 @@
 use anyhow::Result;
+use integration::retry::{retry, RetryConfig};
 use reqwest::Url;
 use serde::Deserialize;
 
-#[derive(Debug, Deserialize)]
+#[cfg(feature = "batch-prove")]
+use prover::eth_types::H256;
+
+#[derive(Clone, Debug, Deserialize)]
 pub struct ChunkInfo {
     pub index: i64,
     pub start_block_number: i64,
@@ -15,9 +19,28 @@ struct ChunksResponse {
     chunks: Option<Vec<ChunkInfo>>,
 }
 
+#[cfg(feature = "batch-prove")]
+#[derive(Debug, Deserialize)]
+pub struct BatchInfo {
+    pub hash: H256,
+    /// Hash of the bundle containing the batch, `None` if not bundled yet.
+    #[cfg(feature = "bundle-prove")]
+    #[serde(default)]
+    pub bundle_hash: Option<H256>,
+}
+
+#[cfg(feature = "batch-prove")]
+#[derive(Debug, Deserialize)]
+struct BatchResponse {
+    batch: Option<BatchInfo>,
+}
+
 pub struct Client {
     id: String,
     chunks_url: String,
+    #[cfg(feature = "batch-prove")]
+    batch_url: String,
+    retry: RetryConfig,
 }
 
 impl Client {
@@ -25,9 +48,53 @@ impl Client {
         Self {
             id: id.to_string(),
             chunks_url: chunks_url.to_string(),
+            #[cfg(feature = "batch-prove")]
+            batch_url: String::new(),
+            retry: RetryConfig::default(),
         }
     }
 
+    /// Retry failed requests with exponential backoff.
+    pub fn with_retry(mut self, retry: RetryConfig) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Url of the batch API, used to look up batch and bundle hashes. Empty means not available.
+    #[cfg(feature = "batch-prove")]
+    pub fn with_batch_url(mut self, batch_url: &str) -> Self {
+        self.batch_url = batch_url.to_string();
+        self
+    }
+
+    #[cfg(feature = "batch-prove")]
+    pub async fn get_batch_info_by_index(&self, batch_index: i64) -> Result<Option<BatchInfo>> {
+        if self.batch_url.is_empty() {
+            return Ok(None);
+        }
+        log::info!("{}: requesting info of batch-{}", self.id, batch_index);
+
+        let url =
+            Url::parse_with_params(&self.batch_url, &[("batch_index", batch_index.to_string())])?;
+
+        let id = format!("{}: get_batch_info_by_index({batch_index})", self.id);
+        let url = &url;
+        let resp: String = retry(&self.retry, &id, move || async move {
+            anyhow::Ok(
+                reqwest::get(url.clone())
+                    .await?
+                    .error_for_status()?
+                    .text()
+                    .await?,
+            )
+        })
+        .await?;
+        log::debug!("{}: rollupscan response = {}", self.id, resp);
+        let resp: BatchResponse = serde_json::from_str(&resp)?;
+
+        Ok(resp.batch)
+    }
+
     pub async fn get_chunk_info_by_batch_index(
         &self,
         batch_index: i64,
@@ -43,7 +110,18 @@ impl Client {
             &[("batch_index", batch_index.to_string())],
         )?;
 
-        let resp: String = reqwest::get(url).await?.text().await?;
+        let id = format!("{}: get_chunk_info_by_batch_index({batch_index})", self.id);
+        let url = &url;
+        let resp: String = retry(&self.retry, &id, move || async move {
+            anyhow::Ok(
+                reqwest::get(url.clone())
+                    .await?
+                    .error_for_status()?
+                    .text()
+                    .await?,
+            )
+        })
+        .await?;
         log::debug!("{}: rollupscan response = {}", self.id, resp);
         let resp: ChunksResponse = serde_json::from_str(&resp)?;
         log::info!(
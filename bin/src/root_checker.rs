@@ -0,0 +1,101 @@
+use crate::{
+    chain_source::ChainSource,
+    failure_ledger::{FailedItem, FailureLedger},
+    prefetch::{PrefetchConfig, TracePrefetcher},
+};
+use integration::root_check::{check_roots, Roots};
+use prover::{read_json_deep, ChunkProofV2};
+use std::sync::Arc;
+
+/// Checks the root continuity of the blocks of `begin_batch..=end_batch`, across chunks and
+/// batches, without proving. Mismatches are logged with the block following them.
+pub async fn check_batches(
+    source: &Arc<ChainSource>,
+    begin_batch: i64,
+    end_batch: i64,
+    prefetch_config: PrefetchConfig,
+    ledger: &FailureLedger,
+) {
+    let mut prefetcher = TracePrefetcher::new(source.clone(), prefetch_config);
+    let (mut num_blocks, mut num_violations) = (0, 0);
+    // Last block of the previous chunk, unknown after a missing block.
+    let mut last_block: Option<Roots> = None;
+    for batch_id in begin_batch..=end_batch {
+        let chunks = match source.get_chunk_info_by_batch_index(batch_id).await {
+            Ok(Some(chunks)) => chunks,
+            Ok(None) => {
+                log::warn!("root_checker: no chunks in batch-{batch_id}");
+                last_block = None;
+                continue;
+            }
+            Err(e) => {
+                ledger.fail(
+                    FailedItem::Batch { batch_id },
+                    e.context(format!(
+                        "root_checker: failed to request rollupscan chunks API for batch-{batch_id}"
+                    )),
+                );
+                last_block = None;
+                continue;
+            }
+        };
+        prefetcher.push_blocks(
+            chunks
+                .iter()
+                .flat_map(|chunk| chunk.start_block_number..=chunk.end_block_number),
+        );
+        for chunk in chunks {
+            let mut roots: Vec<Roots> = last_block.take().into_iter().collect();
+            for _ in chunk.start_block_number..=chunk.end_block_number {
+                let (block_num, trace) = prefetcher.next().await.unwrap();
+                match trace {
+                    Ok(trace) => roots.push(Roots::from_block(&trace)),
+                    Err(e) => {
+                        ledger.fail(
+                            FailedItem::Block { block_num },
+                            e.context(format!(
+                                "root_checker: failed to request l2geth block-trace API for block-{block_num}"
+                            )),
+                        );
+                        // Blocks after the missing one are checked against each other only.
+                        roots.clear();
+                    }
+                }
+            }
+            for violation in check_roots(&roots) {
+                log::error!(
+                    "root_checker: batch-{batch_id} chunk-{}: {violation}",
+                    chunk.index
+                );
+                num_violations += 1;
+            }
+            num_blocks += chunk.end_block_number - chunk.start_block_number + 1;
+            last_block = roots.pop();
+        }
+    }
+    prefetcher.log_stats("root_checker");
+    log::info!(
+        "root_checker: batch-{begin_batch} to batch-{end_batch}: {num_blocks} blocks, {num_violations} violations"
+    );
+}
+
+/// Checks the root continuity of the chunk proofs in the files, in the order of the paths.
+pub fn check_proof_files(paths: &[String]) {
+    let roots: Vec<Roots> = paths
+        .iter()
+        .map(|path| {
+            let proof: ChunkProofV2 = read_json_deep(path)
+                .unwrap_or_else(|e| panic!("root_checker: failed to read chunk proof {path}: {e}"));
+            Roots::from_chunk_proof(&proof)
+        })
+        .collect();
+    let violations = check_roots(&roots);
+    for violation in &violations {
+        log::error!("root_checker: {violation}, in {}", paths[violation.index]);
+    }
+    log::info!(
+        "root_checker: {} chunk proofs, {} violations",
+        paths.len(),
+        violations.len()
+    );
+}
@@ -0,0 +1,187 @@
+use anyhow::Result;
+use prover::{eth_types::l2_types::BlockTrace, RowUsage};
+use serde_derive::Serialize;
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+    time::Duration,
+};
+
+static REPORT_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+/// Enables the run report, appending one json line per record to `path`.
+/// Without calling it, or with an empty path, records are dropped.
+pub fn init(path: &str) {
+    if !path.is_empty() {
+        REPORT_PATH.get_or_init(|| PathBuf::from(path));
+    }
+}
+
+pub fn record(record: &ReportRecord) {
+    let Some(path) = REPORT_PATH.get() else {
+        return;
+    };
+    if let Err(e) = append(path, record) {
+        log::error!("run report: failed to write {}: {e}", path.display());
+    }
+}
+
+fn append(path: &Path, record: &ReportRecord) -> Result<()> {
+    let mut line = serde_json::to_vec(record)?;
+    line.push(b'\n');
+    // One write per line, so that lines of concurrent chunk workers don't interleave.
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(&line)?;
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ReportRecord {
+    Chunk(ChunkRecord),
+    Batch(BatchRecord),
+    Bundle(BundleRecord),
+    Divergence(DivergenceRecord),
+    Simulation(SimulationRecord),
+}
+
+/// Result of proving (or checking) a chunk, batch or bundle.
+#[derive(Debug, Serialize)]
+pub struct Outcome {
+    pub success: bool,
+    pub error: Option<String>,
+    /// `None` if nothing was run, e.g. for batches built in block mode.
+    pub elapsed_ms: Option<u64>,
+}
+
+impl Outcome {
+    pub fn new<T>(result: &Result<T, String>, elapsed: Duration) -> Self {
+        Self {
+            success: result.is_ok(),
+            error: result.as_ref().err().cloned(),
+            elapsed_ms: Some(elapsed.as_millis() as u64),
+        }
+    }
+
+    pub fn succeeded(elapsed: Duration) -> Self {
+        Self::new(&Ok::<(), String>(()), elapsed)
+    }
+
+    pub fn not_run() -> Self {
+        Self {
+            success: true,
+            error: None,
+            elapsed_ms: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChunkRecord {
+    pub batch_id: u64,
+    pub chunk_id: u64,
+    pub begin_block: u64,
+    pub end_block: u64,
+    pub num_txs: usize,
+    pub gas: u64,
+    /// Only known if CCC was run on the chunk.
+    pub row_usage: Option<RowUsage>,
+    /// Sub-circuit with the most rows, and its row number.
+    pub bottleneck: Option<String>,
+    pub bottleneck_rows: Option<usize>,
+    #[serde(flatten)]
+    pub outcome: Outcome,
+}
+
+impl ChunkRecord {
+    pub fn new(
+        batch_id: u64,
+        chunk_id: u64,
+        block_traces: &[BlockTrace],
+        row_usage: Option<RowUsage>,
+        outcome: Outcome,
+    ) -> Self {
+        let block_num = |trace: Option<&BlockTrace>| {
+            trace
+                .and_then(|trace| trace.header.number)
+                .map_or(0, |n| n.as_u64())
+        };
+        let bottleneck = row_usage
+            .as_ref()
+            .and_then(|rows| rows.row_usage_details.iter().max_by_key(|r| r.row_number));
+        Self {
+            batch_id,
+            chunk_id,
+            begin_block: block_num(block_traces.first()),
+            end_block: block_num(block_traces.last()),
+            num_txs: block_traces.iter().map(|b| b.transactions.len()).sum(),
+            gas: block_traces
+                .iter()
+                .map(|b| b.header.gas_used.as_u64())
+                .sum(),
+            bottleneck: bottleneck.map(|r| r.name.clone()),
+            bottleneck_rows: bottleneck.map(|r| r.row_number),
+            row_usage,
+            outcome,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct BatchRecord {
+    /// `None` for batches built in block mode.
+    pub batch_id: Option<i64>,
+    pub num_chunks: usize,
+    pub begin_block: i64,
+    pub end_block: i64,
+    /// Blob bytes used over blob capacity, `None` if the blob couldn't be built.
+    pub blob_utilization: Option<f64>,
+    #[serde(flatten)]
+    pub outcome: Outcome,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BundleRecord {
+    pub first_batch: i64,
+    pub last_batch: i64,
+    pub num_batches: usize,
+    #[serde(flatten)]
+    pub outcome: Outcome,
+}
+
+/// Boundary of a chunk or batch where local chunking differs from the chain.
+#[derive(Debug, Default, Serialize)]
+pub struct DivergenceRecord {
+    /// "chunk" or "batch".
+    pub level: String,
+    pub batch_id: i64,
+    /// `None` for batches.
+    pub chunk_id: Option<i64>,
+    /// Block where the boundaries differ first.
+    pub block_num: i64,
+    pub chain_end_block: i64,
+    /// `None` if not cut locally at all.
+    pub local_end_block: Option<i64>,
+    /// Condition cutting the local one early, `None` if the local one isn't cut early.
+    pub condition: Option<String>,
+}
+
+/// Chunking and batching of a block range under one parameter set of the proposer simulator.
+#[derive(Debug, Serialize)]
+pub struct SimulationRecord {
+    pub max_blocks: Option<usize>,
+    pub max_rows: Option<usize>,
+    pub max_chunks: usize,
+    pub max_blob_bytes: usize,
+    pub num_chunks: usize,
+    pub num_batches: usize,
+    pub num_proofs: usize,
+    pub avg_gas_per_chunk: u64,
+    /// Distribution of blob bytes used over blob capacity among the batches.
+    pub blob_utilization_min: Option<f64>,
+    pub blob_utilization_p50: Option<f64>,
+    pub blob_utilization_p90: Option<f64>,
+    pub blob_utilization_max: Option<f64>,
+}
@@ -0,0 +1,328 @@
+// Dumps block traces of batches from l2geth into `batch_N/chunk_M/block_K.json`, the layout of
+// `integration/tests/extra_traces`, which `test_util::load_chunk` and the replay mode of
+// `chain_prover` read. Tx-by-tx traces go to `chunk_M/txbytx/block_K.json`, and a manifest of
+// each run to `batch_N/manifest.json`.
+
+use anyhow::Result;
+use clap::Parser;
+use integration::{l2geth, retry::RetryConfig, trace_options::TraceOptions};
+use prover::init_env_and_log;
+use serde_derive::Serialize;
+use std::{fs, path::Path, sync::Arc};
+use tokio::{sync::Semaphore, task::JoinHandle};
+
+// The batch API is used by `chain_prover` only.
+#[allow(dead_code)]
+mod rollupscan_client;
+
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct Args {
+    /// l2geth API url.
+    #[clap(long, default_value = "http://127.0.0.1:8545")]
+    l2geth: String,
+    /// rollupscan chunks API url, e.g. `http://127.0.0.1:8560/api/chunks`.
+    #[clap(long)]
+    rollupscan: String,
+    #[clap(long)]
+    begin_batch: i64,
+    /// Same as `begin_batch` if not set.
+    #[clap(long)]
+    end_batch: Option<i64>,
+    /// Only dump the chunks from this index.
+    #[clap(long)]
+    begin_chunk: Option<i64>,
+    /// Only dump the chunks up to this index.
+    #[clap(long)]
+    end_chunk: Option<i64>,
+    /// Dir of the batch dirs.
+    #[clap(long, default_value = "integration/tests/extra_traces")]
+    out_dir: String,
+    /// Max number of l2geth requests in flight.
+    #[clap(long, default_value = "4")]
+    concurrency: usize,
+    /// Max number of blocks in a JSON-RPC batch.
+    #[clap(long, default_value = "16")]
+    batch_size: usize,
+    /// Retries of each l2geth and rollupscan request.
+    #[clap(long, default_value = "5")]
+    max_retries: u32,
+    /// Dump tx-by-tx traces as well.
+    #[clap(long)]
+    txbytx: bool,
+    /// "legacy" or "flatten".
+    #[clap(long, default_value = "legacy")]
+    storage_proof_format: String,
+    /// Hardfork overrides, e.g. "curie=1,darwin=0".
+    #[clap(long, default_value = "")]
+    hardfork_overrides: String,
+    /// Fetch the traces again even if already dumped.
+    #[clap(long)]
+    force: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct Manifest {
+    batch_index: i64,
+    l2geth: String,
+    trace_options: String,
+    chunks: Vec<ChunkEntry>,
+}
+
+#[derive(Debug, Serialize)]
+struct ChunkEntry {
+    index: i64,
+    start_block_number: i64,
+    end_block_number: i64,
+    files: Vec<FileEntry>,
+}
+
+#[derive(Debug, Serialize)]
+struct FileEntry {
+    /// Relative to the chunk dir.
+    path: String,
+    block_num: i64,
+    status: FileStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum FileStatus {
+    Fetched,
+    /// Already dumped by an earlier run.
+    Skipped,
+    Failed,
+}
+
+impl FileEntry {
+    fn new(path: &str, block_num: i64, written: Result<()>) -> Self {
+        let (status, error) = match written {
+            Ok(()) => (FileStatus::Fetched, None),
+            Err(e) => (FileStatus::Failed, Some(format!("{e:#}"))),
+        };
+        Self {
+            path: path.to_string(),
+            block_num,
+            status,
+            error,
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    init_env_and_log("trace_dumper");
+
+    let args = Args::parse();
+    log::info!("trace_dumper: args = {args:?}");
+
+    let retry_config = RetryConfig {
+        max_retries: args.max_retries,
+        ..Default::default()
+    };
+    let l2geth = l2geth::Client::new("trace_dumper", &args.l2geth)
+        .unwrap_or_else(|e| panic!("trace_dumper: failed to initialize ethers Provider: {e}"))
+        .with_retry(retry_config)
+        .with_batch_size(args.batch_size);
+    let l2geth = Arc::new(l2geth);
+    let rollupscan =
+        rollupscan_client::Client::new("trace_dumper", &args.rollupscan).with_retry(retry_config);
+    let options = Arc::new(TraceOptions {
+        storage_proof_format: args.storage_proof_format.parse().unwrap(),
+        overrides: TraceOptions::parse_overrides(&args.hardfork_overrides).unwrap(),
+        ..Default::default()
+    });
+    let semaphore = Arc::new(Semaphore::new(args.concurrency.max(1)));
+
+    let mut num_failed = 0;
+    for batch_index in args.begin_batch..=args.end_batch.unwrap_or(args.begin_batch) {
+        let chunks = match rollupscan.get_chunk_info_by_batch_index(batch_index).await {
+            Ok(Some(chunks)) => chunks,
+            Ok(None) => {
+                log::warn!("trace_dumper: no chunks in batch-{batch_index}");
+                continue;
+            }
+            Err(e) => {
+                log::error!("trace_dumper: failed to request rollupscan chunks API for batch-{batch_index}: {e}");
+                num_failed += 1;
+                continue;
+            }
+        };
+        let batch_dir = Path::new(&args.out_dir).join(format!("batch_{batch_index}"));
+
+        let mut entries = vec![];
+        let mut requests = vec![];
+        for chunk in chunks.iter().filter(|chunk| {
+            args.begin_chunk.map_or(true, |begin| chunk.index >= begin)
+                && args.end_chunk.map_or(true, |end| chunk.index <= end)
+        }) {
+            let chunk_dir = batch_dir.join(format!("chunk_{}", chunk.index));
+            let mut files = vec![];
+            let mut missing = vec![];
+            for block_num in chunk.start_block_number..=chunk.end_block_number {
+                let path = block_path(block_num, false);
+                if !args.force && is_dumped(&chunk_dir.join(&path)) {
+                    files.push(FileEntry {
+                        path,
+                        block_num,
+                        status: FileStatus::Skipped,
+                        error: None,
+                    });
+                } else {
+                    missing.push(block_num);
+                }
+
+                let path = block_path(block_num, true);
+                if !args.txbytx {
+                    continue;
+                }
+                if !args.force && is_dumped(&chunk_dir.join(&path)) {
+                    files.push(FileEntry {
+                        path,
+                        block_num,
+                        status: FileStatus::Skipped,
+                        error: None,
+                    });
+                    continue;
+                }
+                let (l2geth, options, semaphore) =
+                    (l2geth.clone(), options.clone(), semaphore.clone());
+                let chunk_dir = chunk_dir.clone();
+                requests.push((
+                    entries.len(),
+                    vec![(path.clone(), block_num)],
+                    tokio::spawn(async move {
+                        let _permit = semaphore.acquire_owned().await?;
+                        let written = l2geth
+                            .get_txbytx_trace_by_num(block_num, &options)
+                            .await
+                            .and_then(|traces| write_json(&chunk_dir.join(&path), &traces));
+                        anyhow::Ok(vec![FileEntry::new(&path, block_num, written)])
+                    }),
+                ));
+            }
+
+            for blocks in missing.chunks(args.batch_size.max(1)) {
+                let blocks = blocks.to_vec();
+                let (l2geth, options, semaphore) =
+                    (l2geth.clone(), options.clone(), semaphore.clone());
+                let chunk_dir = chunk_dir.clone();
+                let request: JoinHandle<Result<Vec<FileEntry>>> = tokio::spawn(async move {
+                    let _permit = semaphore.acquire_owned().await?;
+                    let traces = l2geth.get_block_traces_by_nums(&blocks, &options).await;
+                    Ok(traces
+                        .into_iter()
+                        .map(|(block_num, trace)| {
+                            let path = block_path(block_num, false);
+                            let written =
+                                trace.and_then(|trace| write_json(&chunk_dir.join(&path), &trace));
+                            FileEntry::new(&path, block_num, written)
+                        })
+                        .collect())
+                });
+                let files = blocks
+                    .iter()
+                    .map(|&block_num| (block_path(block_num, false), block_num))
+                    .collect();
+                requests.push((entries.len(), files, request));
+            }
+
+            entries.push(ChunkEntry {
+                index: chunk.index,
+                start_block_number: chunk.start_block_number,
+                end_block_number: chunk.end_block_number,
+                files,
+            });
+        }
+
+        // Files of a failed request or task are recorded as failed, to be fetched by a later run.
+        for (chunk, request_files, request) in requests {
+            let err = match request.await {
+                Ok(Ok(files)) => {
+                    entries[chunk].files.extend(files);
+                    continue;
+                }
+                Ok(Err(e)) => e,
+                Err(e) => anyhow::Error::new(e).context("task failed"),
+            };
+            let err = format!("{err:#}");
+            entries[chunk]
+                .files
+                .extend(
+                    request_files
+                        .into_iter()
+                        .map(|(path, block_num)| FileEntry {
+                            path,
+                            block_num,
+                            status: FileStatus::Failed,
+                            error: Some(err.clone()),
+                        }),
+                );
+        }
+
+        let (mut fetched, mut skipped, mut failed) = (0, 0, 0);
+        for entry in &mut entries {
+            entry.files.sort_by(|a, b| a.path.cmp(&b.path));
+            for file in &entry.files {
+                match file.status {
+                    FileStatus::Fetched => fetched += 1,
+                    FileStatus::Skipped => skipped += 1,
+                    FileStatus::Failed => {
+                        log::error!(
+                            "trace_dumper: batch-{batch_index} chunk-{} {}: {}",
+                            entry.index,
+                            file.path,
+                            file.error.as_deref().unwrap_or_default()
+                        );
+                        failed += 1;
+                    }
+                }
+            }
+        }
+        num_failed += failed;
+
+        let manifest = Manifest {
+            batch_index,
+            l2geth: args.l2geth.clone(),
+            trace_options: options.to_string(),
+            chunks: entries,
+        };
+        if let Err(e) = write_json(&batch_dir.join("manifest.json"), &manifest) {
+            log::error!("trace_dumper: failed to write manifest of batch-{batch_index}: {e}");
+        }
+        log::info!(
+            "trace_dumper: batch-{batch_index}: {fetched} fetched, {skipped} skipped, {failed} failed"
+        );
+    }
+
+    if num_failed > 0 {
+        log::error!("trace_dumper: {num_failed} failed, run again to fetch them");
+        std::process::exit(1);
+    }
+    log::info!("trace_dumper: END");
+}
+
+// Path relative to the chunk dir.
+fn block_path(block_num: i64, txbytx: bool) -> String {
+    if txbytx {
+        format!("txbytx/block_{block_num}.json")
+    } else {
+        format!("block_{block_num}.json")
+    }
+}
+
+// Empty files are placeholders left by interrupted downloads of older dumpers.
+fn is_dumped(path: &Path) -> bool {
+    fs::metadata(path).map_or(false, |m| m.len() > 0)
+}
+
+// Writes to a temp file first, so that an interrupted write leaves no truncated file behind.
+fn write_json<T: serde::Serialize>(path: &Path, value: &T) -> Result<()> {
+    fs::create_dir_all(path.parent().unwrap())?;
+    let tmp_path = path.with_extension("json.tmp");
+    fs::write(&tmp_path, serde_json::to_vec_pretty(value)?)?;
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
@@ -1,5 +1,5 @@
 use clap::Parser;
-use integration::{prove::prove_and_verify_chunk, test_util::load_chunk};
+use integration::{prove::prove_and_verify_chunk, test_util::load_chunk_checked};
 use prover::{init_env_and_log, ChunkProvingTask};
 use std::env;
 
@@ -29,7 +29,7 @@ fn main() {
 
     let args = Args::parse();
 
-    let traces = load_chunk(&args.trace_path).1;
+    let traces = load_chunk_checked(&args.trace_path).1;
     prover::eth_types::constants::set_scroll_block_constants_with_trace(&traces[0]);
     let chunk = ChunkProvingTask::new(traces);
     let params_map =
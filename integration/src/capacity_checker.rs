@@ -4,13 +4,32 @@ use prover::{
     eth_types::l2_types::BlockTrace, zkevm_circuits::evm_circuit::ExecutionState,
     CircuitCapacityChecker, RowUsage, SubCircuitRowUsage,
 };
-use std::time::Duration;
+use std::{str::FromStr, time::Duration};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CCCMode {
+    /// Rows of the witness block of the whole chunk, same with the prover.
     Optimal,
+    /// A new checker for each block, fed tx by tx. Rows of blocks are summed after normalizing.
     Siger,
+    /// A new checker for each block, fed with the whole block.
     FollowerFull,
+    /// One checker for the whole chunk, fed tx by tx, same with the sequencer.
+    Sequencer,
+}
+
+impl FromStr for CCCMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "optimal" => Ok(Self::Optimal),
+            "signer" => Ok(Self::Siger),
+            "follower_full" => Ok(Self::FollowerFull),
+            "sequencer" => Ok(Self::Sequencer),
+            _ => Err(format!("unknown ccc mode {s}")),
+        }
+    }
 }
 
 pub fn prepare_circuit_capacity_checker() {
@@ -36,6 +55,7 @@ pub fn run_circuit_capacity_checker(
                     CCCMode::Optimal => ccc_by_chunk(batch_id, chunk_id, block_traces),
                     CCCMode::Siger => ccc_as_signer(chunk_id, block_traces),
                     CCCMode::FollowerFull => ccc_as_follower_full(chunk_id, block_traces),
+                    CCCMode::Sequencer => ccc_as_sequencer(chunk_id, block_traces),
                 },
             )
         })
@@ -112,22 +132,18 @@ fn ccc_block_whole_block(
     checker.estimate_circuit_capacity(block.clone()).unwrap();
 }
 
-fn ccc_block_tx_by_tx(checker: &mut CircuitCapacityChecker, block_idx: usize, block: &BlockTrace) {
-    for tx_idx in 0..block.transactions.len() {
-        log::info!("processing {}th block {}th tx", block_idx, tx_idx);
-        #[rustfmt::skip]
-        /*  
-        The capacity_checker is expected to be run inside sequencer, where we don't have the traces of blocks, instead we only have traces of tx.
-        For the "tx_trace":
-            transactions: 
-                the tx itself. For compatibility reasons, transactions is a vector of len 1 now.   
-            execution_results: 
-                tx execution trace. Similar with above, it is also of len 1 vevtor.   
-            storage_trace: 
-                storage_trace is prestate + siblings(or proofs) of touched storage_slots and accounts of this tx.
-        */
-
-        let tx_trace = BlockTrace {
+/// Split a block trace into the traces of its txs, same with what `scroll_getTxByTxBlockTrace`
+/// returns.
+///
+/// The capacity_checker is expected to be run inside sequencer, where we don't have the traces
+/// of blocks, instead we only have traces of tx. For the "tx_trace":
+/// - transactions: the tx itself. For compatibility reasons, transactions is a vector of len 1 now.
+/// - execution_results: tx execution trace. Similar with above, it is also of len 1 vector.
+/// - storage_trace: prestate + siblings(or proofs) of touched storage_slots and accounts of this
+///   tx.
+pub fn split_block_trace_by_tx(block: &BlockTrace) -> Vec<BlockTrace> {
+    (0..block.transactions.len())
+        .map(|tx_idx| BlockTrace {
             transactions: vec![block.transactions[tx_idx].clone()],
             execution_results: vec![block.execution_results[tx_idx].clone()],
             storage_trace: block.tx_storage_trace[tx_idx].clone(),
@@ -137,7 +153,13 @@ fn ccc_block_tx_by_tx(checker: &mut CircuitCapacityChecker, block_idx: usize, bl
             header: block.header.clone(),
             start_l1_queue_index: block.start_l1_queue_index,
             ..Default::default()
-        };
+        })
+        .collect()
+}
+
+fn ccc_block_tx_by_tx(checker: &mut CircuitCapacityChecker, block_idx: usize, block: &BlockTrace) {
+    for (tx_idx, tx_trace) in split_block_trace_by_tx(block).into_iter().enumerate() {
+        log::info!("processing {}th block {}th tx", block_idx, tx_idx);
         log::debug!("calling estimate_circuit_capacity");
         let results = checker.estimate_circuit_capacity(tx_trace).unwrap();
         log::info!("after {}th block {}th tx: {:?}", block_idx, tx_idx, results);
@@ -148,7 +170,8 @@ fn ccc_block_tx_by_tx(checker: &mut CircuitCapacityChecker, block_idx: usize, bl
 fn get_ccc_result_of_chunk(
     chunk_id: u64,
     blocks: &[BlockTrace],
-    by_block: bool, // by block instead of by tx
+    by_block: bool,   // by block instead of by tx
+    persistent: bool, // one checker for the whole chunk instead of one for each block
     norm: bool,
     tag: &str,
 ) -> (RowUsage, Duration) {
@@ -168,7 +191,9 @@ fn get_ccc_result_of_chunk(
     let mut acc_row_usage_normalized = RowUsage::default();
     let mut acc_row_usage_raw = RowUsage::default();
     for (block_idx, block) in blocks.iter().enumerate() {
-        checker.reset();
+        if !persistent {
+            checker.reset();
+        }
 
         if by_block {
             ccc_block_whole_block(&mut checker, block_idx, block);
@@ -176,6 +201,12 @@ fn get_ccc_result_of_chunk(
             ccc_block_tx_by_tx(&mut checker, block_idx, block);
         }
 
+        tx_num += block.transactions.len();
+        // Rows of previous blocks are accumulated in the checker, see below.
+        if persistent {
+            continue;
+        }
+
         let block_result_raw = checker.get_acc_row_usage(false);
 
         log::info!(
@@ -195,8 +226,9 @@ fn get_ccc_result_of_chunk(
         );
         acc_row_usage_raw.add(&block_result_raw);
         acc_row_usage_normalized.add(&block_result_raw.normalize());
-
-        tx_num += block.transactions.len();
+    }
+    if persistent {
+        acc_row_usage_raw = checker.get_acc_row_usage(false);
     }
     log::info!("capacity_checker test done");
     pretty_print_row_usage(&acc_row_usage_raw, blocks, chunk_id, tag);
@@ -272,9 +304,13 @@ pub fn ccc_by_chunk(
 }
 
 pub fn ccc_as_signer(chunk_id: u64, blocks: &[BlockTrace]) -> (RowUsage, Duration) {
-    get_ccc_result_of_chunk(chunk_id, blocks, false, false, "chunk-signer")
+    get_ccc_result_of_chunk(chunk_id, blocks, false, false, false, "chunk-signer")
 }
 
 pub fn ccc_as_follower_full(chunk_id: u64, blocks: &[BlockTrace]) -> (RowUsage, Duration) {
-    get_ccc_result_of_chunk(chunk_id, blocks, true, false, "chunk-f-f")
+    get_ccc_result_of_chunk(chunk_id, blocks, true, false, false, "chunk-f-f")
+}
+
+pub fn ccc_as_sequencer(chunk_id: u64, blocks: &[BlockTrace]) -> (RowUsage, Duration) {
+    get_ccc_result_of_chunk(chunk_id, blocks, false, true, false, "chunk-sequencer")
 }
@@ -0,0 +1,256 @@
+//! Batch codec versions, deciding how the chunks of a batch are encoded into the blob.
+
+use anyhow::{bail, ensure, Result};
+use prover::{get_blob_bytes, BatchData, ChunkInfo, MAX_AGG_SNARKS};
+use std::{io::Read, str::FromStr};
+
+/// Max number of chunks of a codec v1 batch.
+pub const MAX_CHUNKS_V1: usize = 15;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BatchCodec {
+    /// Raw batch bytes, with at most `MAX_CHUNKS_V1` chunks.
+    V1,
+    /// zstd compressed batch bytes, or raw ones without `compressed`. The blob doesn't tell
+    /// which, so batch bytes which don't compress can't be encoded compressed.
+    V2 { compressed: bool },
+    /// Same blob with v2.
+    V3 { compressed: bool },
+    /// A leading byte flagging whether the batch bytes are zstd compressed. With `compressed`
+    /// they are compressed unless that makes them larger, otherwise they are always raw.
+    V4 { compressed: bool },
+}
+
+impl Default for BatchCodec {
+    fn default() -> Self {
+        Self::V4 { compressed: true }
+    }
+}
+
+impl FromStr for BatchCodec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "v1" => Ok(Self::V1),
+            "v2" => Ok(Self::V2 { compressed: true }),
+            "v2-raw" => Ok(Self::V2 { compressed: false }),
+            "v3" => Ok(Self::V3 { compressed: true }),
+            "v3-raw" => Ok(Self::V3 { compressed: false }),
+            "v4" => Ok(Self::V4 { compressed: true }),
+            "v4-raw" => Ok(Self::V4 { compressed: false }),
+            _ => Err(format!("unknown batch codec {s}")),
+        }
+    }
+}
+
+impl BatchCodec {
+    /// Compressed codec of the batch header version. v4 batches are decoded the same whether
+    /// compressed or not.
+    pub fn from_version(version: u8) -> Option<Self> {
+        match version {
+            1 => Some(Self::V1),
+            2 => Some(Self::V2 { compressed: true }),
+            3 => Some(Self::V3 { compressed: true }),
+            4 => Some(Self::default()),
+            _ => None,
+        }
+    }
+
+    /// Version of the batch header.
+    pub fn version(&self) -> u8 {
+        match self {
+            Self::V1 => 1,
+            Self::V2 { .. } => 2,
+            Self::V3 { .. } => 3,
+            Self::V4 { .. } => 4,
+        }
+    }
+
+    pub fn max_chunks(&self) -> usize {
+        match self {
+            Self::V1 => MAX_CHUNKS_V1,
+            _ => MAX_AGG_SNARKS,
+        }
+    }
+
+    /// Max size of the chunks of a batch, i.e. of the data section of the batch bytes.
+    pub fn max_chunk_bytes(&self) -> usize {
+        match self {
+            Self::V1 => BatchData::<MAX_CHUNKS_V1>::n_rows_data(),
+            _ => BatchData::<MAX_AGG_SNARKS>::n_rows_data(),
+        }
+    }
+
+    /// Uncompressed batch bytes, i.e. the metadata of chunk sizes followed by the chunks.
+    /// `chunks` are unpadded.
+    pub fn batch_bytes(&self, chunks: &[ChunkInfo]) -> Vec<u8> {
+        match self {
+            Self::V1 => batch_data_bytes::<MAX_CHUNKS_V1>(chunks),
+            _ => batch_data_bytes::<MAX_AGG_SNARKS>(chunks),
+        }
+    }
+
+    /// Blob bytes of the batch. `chunks` are unpadded. Fails if the codec is compressed but has
+    /// no flag for raw batch bytes, i.e. v2 or v3, and the batch bytes don't compress.
+    pub fn blob_bytes(&self, chunks: &[ChunkInfo]) -> Result<Vec<u8>> {
+        let batch_bytes = self.batch_bytes(chunks);
+        let blob_bytes = match self {
+            Self::V1 | Self::V2 { compressed: false } | Self::V3 { compressed: false } => {
+                batch_bytes
+            }
+            Self::V4 { compressed: false } => std::iter::once(0).chain(batch_bytes).collect(),
+            Self::V2 { compressed: true } | Self::V3 { compressed: true } => {
+                // `get_blob_bytes` is the v4 encoding, which only compresses if that helps.
+                let blob_bytes = get_blob_bytes(&batch_bytes);
+                ensure!(
+                    blob_bytes[0] == 1,
+                    "codec v{}: batch bytes don't compress, encode them with v{}-raw",
+                    self.version(),
+                    self.version()
+                );
+                blob_bytes[1..].to_vec()
+            }
+            Self::V4 { compressed: true } => get_blob_bytes(&batch_bytes),
+        };
+        Ok(blob_bytes)
+    }
+
+    /// Decodes the blob bytes back into the batch. Bytes after the batch, e.g. zero padding of
+    /// the blob, are ignored.
+    pub fn decode_blob(&self, blob_bytes: &[u8]) -> Result<DecodedBlob> {
+        let (compressed, bytes) = match self {
+            Self::V1 => (false, blob_bytes),
+            Self::V2 { compressed } | Self::V3 { compressed } => (*compressed, blob_bytes),
+            Self::V4 { .. } => match blob_bytes.split_first() {
+                Some((0, bytes)) => (false, bytes),
+                Some((1, bytes)) => (true, bytes),
+                Some((flag, _)) => bail!("invalid v4 compression flag {flag}"),
+                None => bail!("empty blob"),
+            },
+        };
+        let batch_bytes = if compressed {
+            decompress(bytes)?
+        } else {
+            bytes.to_vec()
+        };
+        DecodedBlob::from_batch_bytes(compressed, &batch_bytes, self.max_chunks())
+    }
+}
+
+/// Batch recovered from the blob bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedBlob {
+    /// Whether the batch bytes are zstd compressed in the blob.
+    pub compressed: bool,
+    /// Metadata section of the batch bytes, i.e. the number of chunks as u16 followed by the
+    /// size of each chunk slot as u32, all big endian.
+    pub metadata: Vec<u8>,
+    /// Sizes of the valid chunks.
+    pub chunk_sizes: Vec<u32>,
+    /// `tx_bytes` of each valid chunk.
+    pub chunks: Vec<Vec<u8>>,
+}
+
+impl DecodedBlob {
+    fn from_batch_bytes(compressed: bool, batch_bytes: &[u8], max_chunks: usize) -> Result<Self> {
+        let metadata_len = 2 + 4 * max_chunks;
+        ensure!(
+            batch_bytes.len() >= metadata_len,
+            "batch bytes of len {} shorter than metadata of len {metadata_len}",
+            batch_bytes.len()
+        );
+        let (metadata, mut data) = batch_bytes.split_at(metadata_len);
+        let num_chunks = u16::from_be_bytes([metadata[0], metadata[1]]) as usize;
+        ensure!(
+            num_chunks > 0 && num_chunks <= max_chunks,
+            "{num_chunks} chunks out of 1..={max_chunks}"
+        );
+        let chunk_sizes = metadata[2..]
+            .chunks_exact(4)
+            .take(num_chunks)
+            .map(|size| u32::from_be_bytes(size.try_into().unwrap()))
+            .collect::<Vec<_>>();
+        let mut chunks = Vec::with_capacity(num_chunks);
+        for (idx, &size) in chunk_sizes.iter().enumerate() {
+            ensure!(
+                data.len() >= size as usize,
+                "chunk {idx} of size {size} exceeds the remaining {} bytes",
+                data.len()
+            );
+            let (chunk, rest) = data.split_at(size as usize);
+            chunks.push(chunk.to_vec());
+            data = rest;
+        }
+        Ok(Self {
+            compressed,
+            metadata: metadata.to_vec(),
+            chunk_sizes,
+            chunks,
+        })
+    }
+
+    /// Describes the first difference from `other`, chunk by chunk and byte by byte, `None` if
+    /// both are the same.
+    pub fn mismatch(&self, other: &Self) -> Option<String> {
+        if self.compressed != other.compressed {
+            return Some(format!(
+                "compressed {} vs {}",
+                self.compressed, other.compressed
+            ));
+        }
+        if self.chunks.len() != other.chunks.len() {
+            return Some(format!(
+                "{} chunks vs {}",
+                self.chunks.len(),
+                other.chunks.len()
+            ));
+        }
+        if let Some(offset) = first_difference(&self.metadata, &other.metadata) {
+            return Some(format!("metadata differs at byte {offset}"));
+        }
+        self.chunks
+            .iter()
+            .zip(&other.chunks)
+            .enumerate()
+            .find_map(|(idx, (chunk, other_chunk))| {
+                first_difference(chunk, other_chunk).map(|offset| {
+                    format!(
+                        "chunk {idx} of len {} vs {} differs at byte {offset}",
+                        chunk.len(),
+                        other_chunk.len()
+                    )
+                })
+            })
+    }
+}
+
+/// Offset of the first differing byte, or the shorter length if one is a prefix of the other.
+pub fn first_difference(a: &[u8], b: &[u8]) -> Option<usize> {
+    a.iter()
+        .zip(b)
+        .position(|(x, y)| x != y)
+        .or_else(|| (a.len() != b.len()).then(|| a.len().min(b.len())))
+}
+
+// The blob encoder of prover writes a single zstd frame without the magic bytes.
+fn decompress(bytes: &[u8]) -> Result<Vec<u8>> {
+    let mut decoder = zstd::stream::read::Decoder::new(bytes)?.single_frame();
+    decoder.include_magicbytes(false)?;
+    let mut batch_bytes = vec![];
+    decoder.read_to_end(&mut batch_bytes)?;
+    Ok(batch_bytes)
+}
+
+fn batch_data_bytes<const N_SNARKS: usize>(chunks: &[ChunkInfo]) -> Vec<u8> {
+    assert!(
+        !chunks.is_empty() && chunks.len() <= N_SNARKS,
+        "{} chunks out of 1..={N_SNARKS}",
+        chunks.len()
+    );
+    let padded_chunk =
+        ChunkInfo::mock_padded_chunk_info_for_testing(chunks.last().as_ref().unwrap());
+    let chunks_with_padding =
+        [chunks.to_vec(), vec![padded_chunk; N_SNARKS - chunks.len()]].concat();
+    BatchData::<N_SNARKS>::new(chunks.len(), &chunks_with_padding).get_batch_data_bytes()
+}
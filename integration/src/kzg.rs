@@ -0,0 +1,106 @@
+//! KZG commitment to the blob of a batch, i.e. what the batch commits to L1.
+
+use crate::codec::{BatchCodec, MAX_CHUNKS_V1};
+use anyhow::{anyhow, ensure, Result};
+use c_kzg::{Blob, Bytes32, KzgCommitment, KzgProof, BYTES_PER_BLOB};
+use prover::{eth_types::H256, BatchHeader, ChunkInfo, MAX_AGG_SNARKS};
+use sha2::{Digest, Sha256};
+
+/// Version byte of the versioned hash of a KZG commitment, see EIP-4844.
+const VERSIONED_HASH_VERSION_KZG: u8 = 0x01;
+
+/// Blob bytes packed into each field element, whose most significant byte is left zero.
+const BYTES_PER_FIELD_ELEMENT: usize = 31;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlobCommitment {
+    /// KZG commitment, 48 bytes.
+    pub commitment: Vec<u8>,
+    /// `blobVersionedHash` of the batch on L1.
+    pub versioned_hash: H256,
+    /// Point where the blob polynomial is evaluated, as checked by the batch circuit.
+    pub challenge: H256,
+    pub evaluation: H256,
+    /// KZG proof of the evaluation, 48 bytes.
+    pub proof: Vec<u8>,
+}
+
+/// Commits to the blob of the batch of `chunks` in the codec. `chunks` are unpadded.
+///
+/// The versioned hash, challenge and evaluation are those of the batch header built by prover,
+/// which are checked against the commitment and proof computed by c-kzg. The header lays out
+/// `MAX_AGG_SNARKS` chunks, so codec v1 blobs, of `MAX_CHUNKS_V1` chunks, are not supported.
+pub fn blob_commitment(codec: BatchCodec, chunks: &[ChunkInfo]) -> Result<BlobCommitment> {
+    ensure!(
+        codec != BatchCodec::V1,
+        "codec v1 blobs of {MAX_CHUNKS_V1} chunks are not supported"
+    );
+    let blob_bytes = codec.blob_bytes(chunks)?;
+    // Only the blob fields of the header are used.
+    let header = BatchHeader::<MAX_AGG_SNARKS>::construct_from_chunks(
+        codec.version(),
+        0,
+        0,
+        0,
+        H256::zero(),
+        0,
+        chunks,
+        &blob_bytes,
+    );
+    let [challenge, evaluation] = header.blob_data_proof;
+
+    let blob = to_kzg_blob(&blob_bytes)?;
+    let settings = c_kzg::ethereum_kzg_settings();
+    let commitment = KzgCommitment::blob_to_kzg_commitment(&blob, settings)
+        .map_err(|e| anyhow!("failed to commit to blob: {e:?}"))?
+        .to_bytes();
+    let versioned_hash = versioned_hash(&commitment[..]);
+    ensure!(
+        versioned_hash == header.blob_versioned_hash,
+        "versioned hash {versioned_hash:?} of the commitment differs from {:?} of the header",
+        header.blob_versioned_hash
+    );
+
+    let z = Bytes32::from_bytes(challenge.as_bytes())
+        .map_err(|e| anyhow!("invalid challenge {challenge:?}: {e:?}"))?;
+    let (proof, y) = KzgProof::compute_kzg_proof(&blob, &z, settings)
+        .map_err(|e| anyhow!("failed to prove evaluation: {e:?}"))?;
+    ensure!(
+        H256::from_slice(&y[..]) == evaluation,
+        "evaluation {:?} at the challenge differs from {evaluation:?} of the header",
+        H256::from_slice(&y[..])
+    );
+
+    Ok(BlobCommitment {
+        commitment: commitment.to_vec(),
+        versioned_hash,
+        challenge,
+        evaluation,
+        proof: proof.to_bytes().to_vec(),
+    })
+}
+
+/// Versioned hash of a KZG commitment, see EIP-4844.
+pub fn versioned_hash(commitment: &[u8]) -> H256 {
+    let mut hash: [u8; 32] = Sha256::digest(commitment).into();
+    hash[0] = VERSIONED_HASH_VERSION_KZG;
+    H256(hash)
+}
+
+// Packs the blob bytes into field elements, 31 bytes each, padded with zeros.
+fn to_kzg_blob(blob_bytes: &[u8]) -> Result<Blob> {
+    let max_len = BYTES_PER_BLOB / 32 * BYTES_PER_FIELD_ELEMENT;
+    ensure!(
+        blob_bytes.len() <= max_len,
+        "blob bytes of len {} exceed {max_len}",
+        blob_bytes.len()
+    );
+    let mut blob = vec![0u8; BYTES_PER_BLOB];
+    for (elem, bytes) in blob
+        .chunks_exact_mut(32)
+        .zip(blob_bytes.chunks(BYTES_PER_FIELD_ELEMENT))
+    {
+        elem[1..=bytes.len()].copy_from_slice(bytes);
+    }
+    Blob::from_bytes(&blob).map_err(|e| anyhow!("invalid blob: {e:?}"))
+}
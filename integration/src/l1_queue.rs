@@ -0,0 +1,144 @@
+//! Continuity of the L1 message queue over block traces, where the nonce of an L1 message is its
+//! queue index.
+
+use prover::eth_types::l2_types::BlockTrace;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueueViolation {
+    /// The block doesn't follow the previous one, hiding the messages of the missing blocks.
+    BlockGap { block_num: u64, prev_block_num: u64 },
+    /// The block starts the queue elsewhere than where the previous block ends.
+    Gap {
+        block_num: u64,
+        expected: u64,
+        start_l1_queue_index: u64,
+    },
+    /// An L1 message at a queue index already popped, i.e. popped twice or out of order.
+    Overlap {
+        block_num: u64,
+        tx_index: usize,
+        queue_index: u64,
+        expected: u64,
+    },
+    /// Messages `from..to` are skipped before the L1 message at `to`, which is legitimate, e.g.
+    /// for messages the sequencer skips as they exceed the circuit capacity.
+    Skipped {
+        block_num: u64,
+        tx_index: usize,
+        from: u64,
+        to: u64,
+    },
+}
+
+impl fmt::Display for QueueViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BlockGap {
+                block_num,
+                prev_block_num,
+            } => write!(f, "block-{block_num} follows block-{prev_block_num}"),
+            Self::Gap {
+                block_num,
+                expected,
+                start_l1_queue_index,
+            } => write!(
+                f,
+                "block-{block_num} starts at queue index {start_l1_queue_index}, expected {expected}"
+            ),
+            Self::Overlap {
+                block_num,
+                tx_index,
+                queue_index,
+                expected,
+            } => write!(
+                f,
+                "block-{block_num} tx-{tx_index} pops queue index {queue_index} again, expected {expected}"
+            ),
+            Self::Skipped {
+                block_num,
+                tx_index,
+                from,
+                to,
+            } => write!(
+                f,
+                "block-{block_num} tx-{tx_index} skips queue indices {from}..{to}"
+            ),
+        }
+    }
+}
+
+/// Checks the L1 messages of the traces, e.g. of a chunk, batch or block range, for gaps and
+/// overlaps. `expected_start` is the next queue index before the first block if known, e.g. from
+/// the previous chunk.
+///
+/// The traces are of consecutive blocks, a missing block is a violation by itself. Separate block
+/// ranges should be checked one by one.
+///
+/// Returns the next queue index after the last block, with the skipped messages, which don't
+/// break the queue.
+pub fn check_l1_queue<'a>(
+    traces: impl IntoIterator<Item = &'a BlockTrace>,
+    expected_start: Option<u64>,
+) -> Result<(u64, Vec<QueueViolation>), Vec<QueueViolation>> {
+    let mut violations = vec![];
+    let mut skipped = vec![];
+    let mut expected = expected_start;
+    let mut prev_block_num = None;
+    for trace in traces {
+        let block_num = trace.header.number.map_or(0, |n| n.as_u64());
+        match prev_block_num {
+            Some(prev_block_num) if prev_block_num + 1 != block_num => {
+                violations.push(QueueViolation::BlockGap {
+                    block_num,
+                    prev_block_num,
+                })
+            }
+            _ => {}
+        }
+        match expected {
+            Some(expected) if expected != trace.start_l1_queue_index => {
+                violations.push(QueueViolation::Gap {
+                    block_num,
+                    expected,
+                    start_l1_queue_index: trace.start_l1_queue_index,
+                })
+            }
+            _ => {}
+        }
+
+        let mut next = trace.start_l1_queue_index;
+        for (tx_index, tx) in trace.transactions.iter().enumerate() {
+            if !tx.is_l1_tx() {
+                continue;
+            }
+            let queue_index = tx.nonce;
+            if queue_index < next {
+                violations.push(QueueViolation::Overlap {
+                    block_num,
+                    tx_index,
+                    queue_index,
+                    expected: next,
+                });
+                continue;
+            }
+            if queue_index > next {
+                skipped.push(QueueViolation::Skipped {
+                    block_num,
+                    tx_index,
+                    from: next,
+                    to: queue_index,
+                });
+            }
+            next = queue_index + 1;
+        }
+        expected = Some(next);
+        prev_block_num = Some(block_num);
+    }
+
+    if violations.is_empty() {
+        Ok((expected.unwrap_or_default(), skipped))
+    } else {
+        Err(violations)
+    }
+}
@@ -1,11 +1,28 @@
-use anyhow::Result;
+use crate::{
+    retry::{retry, RetryConfig},
+    trace_cache::{CacheMode, CachedTrace, TraceCache, TraceKind},
+    trace_check::ensure_block_trace,
+    trace_options::TraceOptions,
+};
+use anyhow::{anyhow, Result};
 use ethers_providers::{Http, Middleware, Provider};
 use prover::eth_types::l2_types::BlockTrace;
-use serde::Serialize;
+use reqwest::Url;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::future::Future;
+use tokio::sync::OnceCell;
 
 pub struct Client {
     id: String,
     provider: Provider<Http>,
+    // Batch requests are sent directly, since the provider doesn't support them.
+    http: reqwest::Client,
+    api_url: Url,
+    retry: RetryConfig,
+    batch_size: usize,
+    cache: Option<TraceCache>,
+    chain_id: OnceCell<u64>,
 }
 
 impl Client {
@@ -15,58 +32,368 @@ impl Client {
         Ok(Self {
             id: id.to_string(),
             provider,
+            http: reqwest::Client::new(),
+            api_url: api_url.parse()?,
+            retry: RetryConfig::default(),
+            batch_size: 1,
+            cache: None,
+            chain_id: OnceCell::new(),
         })
     }
 
-    pub async fn get_block_number(&self) -> Result<u64> {
-        Ok(self.provider.get_block_number().await?.as_u64())
-    }
-    pub async fn get_txbytx_trace_by_num(&self, block_num: i64) -> Result<Vec<BlockTrace>> {
-        let params =
-            serde_json::json!([format!("{block_num:#x}"), {"StorageProofFormat": "legacy"}]);
-        let trace = self
-            .provider
-            .request("scroll_getTxByTxBlockTrace", params)
-            .await?;
+    /// Max number of requests in a JSON-RPC batch of `get_block_traces_by_nums`.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Cache traces on disk, see `TraceCache`.
+    pub fn with_cache(mut self, cache: TraceCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Chain id of the cached traces, requested from l2geth if not set. Needed for the
+    /// cache-only mode without l2geth.
+    pub fn with_chain_id(self, chain_id: u64) -> Self {
+        Self {
+            chain_id: OnceCell::new_with(Some(chain_id)),
+            ..self
+        }
+    }
+
+    pub async fn get_chain_id(&self) -> Result<u64> {
+        self.chain_id
+            .get_or_try_init(|| async {
+                let id = format!("{}: get_chain_id", self.id);
+                retry(&self.retry, &id, move || async move {
+                    anyhow::Ok(self.provider.get_chainid().await?.as_u64())
+                })
+                .await
+            })
+            .await
+            .copied()
+    }
+
+    // Serves the traces of the block from the cache or `fetch`, according to the cache mode.
+    async fn cached<T, F, Fut>(
+        &self,
+        block_num: i64,
+        options: &str,
+        kind: TraceKind,
+        fetch: F,
+    ) -> Result<T>
+    where
+        T: CachedTrace,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        if let Some(trace) = self.read_cache(block_num, options, kind).await? {
+            return Ok(trace);
+        }
+        let trace = fetch().await?;
+        self.write_cache(block_num, options, kind, &trace).await;
         Ok(trace)
     }
 
-    // when override_curie == true,
-    //   we will force curie hard fork when tracing
+    // Cached traces of the block, `None` if to be fetched according to the cache mode.
+    async fn read_cache<T: CachedTrace>(
+        &self,
+        block_num: i64,
+        options: &str,
+        kind: TraceKind,
+    ) -> Result<Option<T>> {
+        let Some(cache) = &self.cache else {
+            return Ok(None);
+        };
+        let block_num = block_num as u64;
+        let path = cache.path(self.get_chain_id().await?, options, kind, block_num);
+        let mode = cache.mode();
+        if !mode.reads() {
+            return Ok(None);
+        }
+        match cache.read::<T>(&path, block_num) {
+            Ok(Some(trace)) => Ok(Some(trace)),
+            Ok(None) if !mode.fetches() => Err(anyhow!(
+                "{}: block {block_num} not in trace cache {}",
+                self.id,
+                path.display()
+            )),
+            Err(e) if !mode.fetches() => Err(e),
+            Err(e) => {
+                log::warn!("{}: {e:#}, fetching again", self.id);
+                Ok(None)
+            }
+            Ok(None) => Ok(None),
+        }
+    }
+
+    // Caches the fetched traces of the block, according to the cache mode.
+    async fn write_cache<T: CachedTrace>(
+        &self,
+        block_num: i64,
+        options: &str,
+        kind: TraceKind,
+        trace: &T,
+    ) {
+        let Some(cache) = &self.cache else {
+            return;
+        };
+        let block_num = block_num as u64;
+        let chain_id = match self.get_chain_id().await {
+            Ok(chain_id) => chain_id,
+            Err(e) => {
+                log::warn!("{}: failed to cache block {block_num}: {e}", self.id);
+                return;
+            }
+        };
+        let path = cache.path(chain_id, options, kind, block_num);
+        // Write-through keeps the traces already cached.
+        let keep = matches!(cache.mode(), CacheMode::WriteThrough)
+            && matches!(cache.read::<T>(&path, block_num), Ok(Some(_)));
+        if !keep {
+            if let Err(e) = cache.write(&path, trace) {
+                log::warn!("{}: failed to cache {}: {e}", self.id, path.display());
+            }
+        }
+    }
+
+    /// Retry failed requests with exponential backoff.
+    pub fn with_retry(mut self, retry: RetryConfig) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub async fn get_block_number(&self) -> Result<u64> {
+        let id = format!("{}: get_block_number", self.id);
+        retry(&self.retry, &id, move || async move {
+            anyhow::Ok(self.provider.get_block_number().await?.as_u64())
+        })
+        .await
+    }
+
+    pub async fn get_txbytx_trace_by_num(
+        &self,
+        block_num: i64,
+        options: &TraceOptions,
+    ) -> Result<Vec<BlockTrace>> {
+        let params = trace_params(block_num, options);
+        let id = format!("{}: get_txbytx_trace_by_num({block_num})", self.id);
+        let params = &params;
+        let key = options.cache_key();
+        self.cached(block_num, &key, TraceKind::TxByTx, || {
+            retry(&self.retry, &id, move || async move {
+                anyhow::Ok(
+                    self.provider
+                        .request("scroll_getTxByTxBlockTrace", params.clone())
+                        .await?,
+                )
+            })
+        })
+        .await
+    }
+
     pub async fn get_block_trace_by_num(
         &self,
         block_num: i64,
-        override_curie: bool,
+        options: &TraceOptions,
     ) -> Result<BlockTrace> {
         log::info!("{}: requesting trace of block {}", self.id, block_num);
 
-        let params = if override_curie {
-            // curl -s -H 'Content-Type: application/json' -X POST --data
-            // '{"jsonrpc":"2.0","method":"scroll_getBlockTraceByNumberOrHash",
-            // "params": ["0x485490", {"overrides": {"curieBlock":1}}], "id": 99}'
-            // 127.0.0.1:8545
-            #[derive(Serialize)]
-            struct ChainConfig {
-                #[serde(rename = "curieBlock")]
-                curie_block: usize,
+        let params = trace_params(block_num, options);
+        let id = format!("{}: get_block_trace_by_num({block_num})", self.id);
+        let params = &params;
+        let key = options.cache_key();
+        self.cached(block_num, &key, TraceKind::Block, || {
+            retry(&self.retry, &id, move || async move {
+                let trace: BlockTrace = self
+                    .provider
+                    .request("scroll_getBlockTraceByNumberOrHash", params.clone())
+                    .await?;
+                // Bad traces are requested again, and never cached.
+                ensure_block_trace(&trace, block_num as u64, self.chain_id.get().copied())?;
+                anyhow::Ok(trace)
+            })
+        })
+        .await
+    }
+
+    /// Traces of the blocks, requested in JSON-RPC batches of up to the batch size, in the order
+    /// of `block_nums`. Failed blocks are retried in smaller batches of their own, while the
+    /// traces of the other blocks are kept.
+    pub async fn get_block_traces_by_nums(
+        &self,
+        block_nums: &[i64],
+        options: &TraceOptions,
+    ) -> Vec<(i64, Result<BlockTrace>)> {
+        let key = options.cache_key();
+        let mut traces = Vec::with_capacity(block_nums.len());
+        let mut missing = vec![];
+        for (i, &block_num) in block_nums.iter().enumerate() {
+            let cached = self.read_cache(block_num, &key, TraceKind::Block).await;
+            if matches!(cached, Ok(None)) {
+                missing.push(i);
             }
-            #[derive(Serialize)]
-            struct TraceConfig {
-                overrides: ChainConfig,
+            traces.push(cached.transpose());
+        }
+
+        for batch in missing.chunks(self.batch_size) {
+            let (first, last) = (block_nums[batch[0]], block_nums[*batch.last().unwrap()]);
+            log::info!(
+                "{}: requesting traces of {} blocks {first}..={last}",
+                self.id,
+                batch.len()
+            );
+            let params: Vec<_> = batch
+                .iter()
+                .map(|&i| trace_params(block_nums[i], options))
+                .collect();
+            let id = format!("{}: get_block_traces_by_nums({first}..={last})", self.id);
+            let fetched = self
+                .batch_request(
+                    &id,
+                    "scroll_getBlockTraceByNumberOrHash",
+                    &params,
+                    |j, trace: &BlockTrace| {
+                        let block_num = block_nums[batch[j]] as u64;
+                        ensure_block_trace(trace, block_num, self.chain_id.get().copied())
+                    },
+                )
+                .await;
+            for (&i, trace) in batch.iter().zip(fetched) {
+                if let Ok(trace) = &trace {
+                    self.write_cache(block_nums[i], &key, TraceKind::Block, trace)
+                        .await;
+                }
+                traces[i] = Some(trace);
             }
-            let override_param = TraceConfig {
-                overrides: ChainConfig {
-                    curie_block: 1, // any small value could be ok
-                },
-            };
-            serde_json::json!([format!("{block_num:#x}"), override_param])
-        } else {
-            serde_json::json!([format!("{block_num:#x}"), {"StorageProofFormat": "legacy"}])
+        }
+
+        block_nums
+            .iter()
+            .copied()
+            .zip(traces.into_iter().map(Option::unwrap))
+            .collect()
+    }
+
+    // Sends the requests in a JSON-RPC batch, and resends the failed ones with the backoff of the
+    // retry config. Results failing `check` of their request index count as failed. Results are in
+    // the order of `params`.
+    async fn batch_request<T: DeserializeOwned>(
+        &self,
+        id: &str,
+        method: &str,
+        params: &[Value],
+        check: impl Fn(usize, &T) -> Result<()>,
+    ) -> Vec<Result<T>> {
+        let mut results: Vec<Option<Result<T>>> = params.iter().map(|_| None).collect();
+        let mut pending: Vec<usize> = (0..params.len()).collect();
+        let mut backoff = self.retry.initial_backoff;
+        let mut retries = 0;
+        loop {
+            let responses = self
+                .send_batch(method, pending.iter().map(|&i| &params[i]))
+                .await;
+            let mut failed = vec![];
+            for (i, res) in pending.into_iter().zip(responses) {
+                let res = res.and_then(|res| check(i, &res).map(|()| res));
+                if let Err(e) = &res {
+                    if retries < self.retry.max_retries {
+                        log::warn!("{id}: request {i} failed: {e}");
+                    }
+                    failed.push(i);
+                }
+                results[i] = Some(res);
+            }
+            if failed.is_empty() || retries >= self.retry.max_retries {
+                break;
+            }
+            retries += 1;
+            log::warn!(
+                "{id}: {} of {} requests failed, retry {retries}/{} in {backoff:?}",
+                failed.len(),
+                params.len(),
+                self.retry.max_retries
+            );
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(self.retry.max_backoff);
+            pending = failed;
+        }
+
+        results
+            .into_iter()
+            .map(|res| {
+                res.unwrap()
+                    .map_err(|e| e.context(format!("{id}: gave up after {retries} retries")))
+            })
+            .collect()
+    }
+
+    async fn send_batch<'a, T: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: impl Iterator<Item = &'a Value>,
+    ) -> Vec<Result<T>> {
+        let requests: Vec<_> = params
+            .enumerate()
+            .map(|(id, params)| {
+                serde_json::json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params})
+            })
+            .collect();
+        let responses = async {
+            let response = self
+                .http
+                .post(self.api_url.clone())
+                .json(&requests)
+                .send()
+                .await?
+                .error_for_status()?;
+            anyhow::Ok(response.json::<Vec<Value>>().await?)
+        }
+        .await;
+        match responses {
+            Ok(responses) => split_batch_response(requests.len(), responses),
+            Err(e) => requests
+                .iter()
+                .map(|_| Err(anyhow!("batch request failed: {e}")))
+                .collect(),
+        }
+    }
+}
+
+/// Maps the responses of a JSON-RPC batch back to its requests, where the id of a request is its
+/// index in the batch. Responses may come in any order, and missing ones are errors.
+pub fn split_batch_response<T: DeserializeOwned>(
+    num_requests: usize,
+    responses: Vec<Value>,
+) -> Vec<Result<T>> {
+    let mut results: Vec<Result<T>> = (0..num_requests)
+        .map(|id| Err(anyhow!("no response to request {id}")))
+        .collect();
+    for mut response in responses {
+        let Some(id) = response
+            .get("id")
+            .and_then(Value::as_u64)
+            .map(|id| id as usize)
+            .filter(|id| *id < num_requests)
+        else {
+            log::warn!("response to unknown request {:?}", response.get("id"));
+            continue;
+        };
+        results[id] = match (response.get("error"), response.get_mut("result")) {
+            (Some(error), _) => Err(anyhow!("request {id} failed: {error}")),
+            (None, Some(result)) => serde_json::from_value(result.take())
+                .map_err(|e| anyhow!("invalid response to request {id}: {e}")),
+            (None, None) => Err(anyhow!("no result in response to request {id}")),
         };
-        let trace = self
-            .provider
-            .request("scroll_getBlockTraceByNumberOrHash", params)
-            .await?;
-        Ok(trace)
     }
+    results
+}
+
+// curl -s -H 'Content-Type: application/json' -X POST --data
+// '{"jsonrpc":"2.0","method":"scroll_getBlockTraceByNumberOrHash",
+// "params": ["0x485490", {"overrides": {"curieBlock":1}}], "id": 99}'
+// 127.0.0.1:8545
+fn trace_params(block_num: i64, options: &TraceOptions) -> Value {
+    serde_json::json!([format!("{block_num:#x}"), options.to_param()])
 }
@@ -1,6 +1,16 @@
 pub mod capacity_checker;
+pub mod codec;
+pub mod constants;
+pub mod kzg;
+pub mod l1_queue;
 pub mod l2geth;
 pub mod mock;
+pub mod proposer;
 pub mod prove;
+pub mod retry;
+pub mod root_check;
 pub mod test_util;
+pub mod trace_cache;
+pub mod trace_check;
+pub mod trace_options;
 mod verifier;
@@ -0,0 +1,568 @@
+//! Chunk and batch proposing from block traces, same with the production "chunk proposer" and
+//! "batch proposer" given the same limits.
+
+use crate::{
+    capacity_checker::{split_block_trace_by_tx, CCCMode},
+    codec::BatchCodec,
+    constants::N_BLOB_BYTES,
+};
+use anyhow::Result;
+use ethers_core::utils::keccak256;
+use prover::{
+    calculate_row_usage_of_witness_block, chunk_trace_to_witness_block,
+    eth_types::{l2_types::BlockTrace, H256},
+    read_env_var, ChunkInfo, CircuitCapacityChecker, RowUsage, MAX_AGG_SNARKS,
+};
+
+/// Limits of a chunk. `None` means unlimited.
+#[derive(Debug, Clone)]
+pub struct ChunkLimits {
+    pub max_blocks: Option<usize>,
+    /// Sum of gas used by the blocks.
+    pub max_gas: Option<u64>,
+    pub max_l1_messages: Option<u64>,
+    /// Sum of calldata bytes of the txs.
+    pub max_tx_payload_bytes: Option<usize>,
+    /// Max rows of any sub-circuit after normalizing, instead of the capacity of the circuits.
+    /// Only checked with a `ccc_mode`.
+    pub max_rows: Option<usize>,
+    /// How to check circuit capacity, `None` to not check it. The caller should have called
+    /// `capacity_checker::prepare_circuit_capacity_checker` then. `CCCMode::FollowerFull` by
+    /// default, i.e. a new checker for each block with the row usage of the blocks summed.
+    pub ccc_mode: Option<CCCMode>,
+}
+
+impl Default for ChunkLimits {
+    fn default() -> Self {
+        Self {
+            max_blocks: None,
+            max_gas: None,
+            max_l1_messages: None,
+            max_tx_payload_bytes: None,
+            max_rows: None,
+            ccc_mode: Some(CCCMode::FollowerFull),
+        }
+    }
+}
+
+/// Why a chunk ends where it does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChunkCut {
+    MaxBlocks,
+    MaxGas,
+    MaxL1Messages,
+    MaxTxPayloadBytes,
+    /// The next block would make the named sub-circuit run out of rows.
+    CircuitCapacity(String),
+    /// The pending blocks are taken by `ChunkBuilder::finish`.
+    Finish,
+}
+
+/// A chunk built by `ChunkBuilder`.
+#[derive(Debug)]
+pub struct ProposedChunk {
+    pub traces: Vec<BlockTrace>,
+    pub cut: ChunkCut,
+    /// Normalized row usage as estimated by the `CCCMode`, default if not checked.
+    pub row_usage: RowUsage,
+}
+
+/// Usage of the limited resources by a block or a chunk.
+#[derive(Debug, Clone, Copy, Default)]
+struct ChunkUsage {
+    gas: u64,
+    l1_messages: u64,
+    tx_payload_bytes: usize,
+}
+
+impl ChunkUsage {
+    fn of_block(trace: &BlockTrace) -> Self {
+        Self {
+            gas: trace.header.gas_used.as_u64(),
+            l1_messages: trace.num_l1_txs(),
+            tx_payload_bytes: trace.transactions.iter().map(|tx| tx.data.len()).sum(),
+        }
+    }
+
+    fn add(&mut self, other: &Self) {
+        self.gas += other.gas;
+        self.l1_messages += other.l1_messages;
+        self.tx_payload_bytes += other.tx_payload_bytes;
+    }
+
+    /// The first exceeded limit, if any.
+    fn exceeded(&self, limits: &ChunkLimits) -> Option<ChunkCut> {
+        if limits.max_gas.is_some_and(|max| self.gas > max) {
+            Some(ChunkCut::MaxGas)
+        } else if limits
+            .max_l1_messages
+            .is_some_and(|max| self.l1_messages > max)
+        {
+            Some(ChunkCut::MaxL1Messages)
+        } else if limits
+            .max_tx_payload_bytes
+            .is_some_and(|max| self.tx_payload_bytes > max)
+        {
+            Some(ChunkCut::MaxTxPayloadBytes)
+        } else {
+            None
+        }
+    }
+}
+
+/// Builds chunks from consecutive blocks.
+///
+/// A block which doesn't fit into the pending chunk starts a new one, and a chunk is complete
+/// once it has `max_blocks` blocks. A single block exceeding the limits makes a chunk by itself.
+pub struct ChunkBuilder {
+    limits: ChunkLimits,
+    traces: Vec<BlockTrace>,
+    usage: ChunkUsage,
+    acc_row_usage_normalized: RowUsage,
+    // Kept across the blocks of the pending chunk with `CCCMode::Sequencer`.
+    checker: Option<CircuitCapacityChecker>,
+}
+
+impl ChunkBuilder {
+    pub fn new(limits: ChunkLimits) -> Self {
+        Self {
+            limits,
+            traces: Vec::new(),
+            usage: ChunkUsage::default(),
+            acc_row_usage_normalized: RowUsage::default(),
+            checker: None,
+        }
+    }
+
+    /// Restores the pending chunk, e.g. from a checkpoint of `pending` and `row_usage`.
+    pub fn restore(&mut self, traces: Vec<BlockTrace>, acc_row_usage_normalized: RowUsage) {
+        self.clear();
+        for trace in &traces {
+            self.usage.add(&ChunkUsage::of_block(trace));
+        }
+        if self.limits.ccc_mode == Some(CCCMode::Sequencer) {
+            // The checker itself isn't checkpointed, so replay the blocks.
+            let checker = self.checker.insert(CircuitCapacityChecker::new());
+            for trace in &traces {
+                estimate_block(checker, trace, true);
+            }
+        }
+        self.traces = traces;
+        self.acc_row_usage_normalized = acc_row_usage_normalized;
+    }
+
+    /// Blocks of the pending chunk.
+    pub fn pending(&self) -> &[BlockTrace] {
+        &self.traces
+    }
+
+    /// Accumulated row usage of the pending chunk, only tracked with a `ccc_mode`.
+    pub fn row_usage(&self) -> &RowUsage {
+        &self.acc_row_usage_normalized
+    }
+
+    /// Adds the next block. Returns a chunk if one is complete, which includes the block if the
+    /// block limit is reached and doesn't if the block exceeds other limits.
+    pub fn add(&mut self, trace: BlockTrace) -> Option<ProposedChunk> {
+        let mut usage = self.usage;
+        usage.add(&ChunkUsage::of_block(&trace));
+        let mut cut = usage.exceeded(&self.limits);
+        let mut row_usage = None;
+        // A block starting a chunk is checked even over other limits, for its row usage.
+        if cut.is_none() || self.traces.is_empty() {
+            if let Some(acc_row_usage) = self.estimate_capacity(&trace) {
+                cut = cut.or_else(|| overflowed_circuit(&acc_row_usage, self.limits.max_rows));
+                row_usage = Some(acc_row_usage);
+            }
+        }
+
+        // Build a chunk with PREV traces, and start the next one with the block.
+        let chunk = match cut {
+            Some(cut) if !self.traces.is_empty() => {
+                let chunk = self.take_pending(cut);
+                usage = ChunkUsage::of_block(&trace);
+                row_usage = self.estimate_capacity(&trace);
+                Some(chunk)
+            }
+            _ => None,
+        };
+        self.traces.push(trace);
+        self.usage = usage;
+        if let Some(row_usage) = row_usage {
+            self.acc_row_usage_normalized = row_usage;
+        }
+
+        // Pending traces are less than `max_blocks` after a chunk is built above, as a full chunk
+        // is built right away.
+        if let Some(max_blocks) = self.limits.max_blocks {
+            if self.traces.len() >= max_blocks {
+                debug_assert!(chunk.is_none());
+                return Some(self.take_pending(ChunkCut::MaxBlocks));
+            }
+        }
+        chunk
+    }
+
+    /// Takes the pending blocks as the last chunk, e.g. at the end of a block range.
+    pub fn finish(&mut self) -> Option<ProposedChunk> {
+        (!self.traces.is_empty()).then(|| self.take_pending(ChunkCut::Finish))
+    }
+
+    /// Drops the pending blocks, e.g. when they are reorged.
+    pub fn reset(&mut self) {
+        self.clear();
+    }
+
+    /// Accumulated row usage of the pending chunk with `trace` added, `None` without a
+    /// `ccc_mode`. Only the checker of `CCCMode::Sequencer` keeps the block.
+    fn estimate_capacity(&mut self, trace: &BlockTrace) -> Option<RowUsage> {
+        let mode = self.limits.ccc_mode?;
+        let mut acc_row_usage = self.acc_row_usage_normalized.clone();
+        match mode {
+            CCCMode::Optimal => {
+                let traces = self.traces.iter().chain(std::iter::once(trace));
+                let witness_block = chunk_trace_to_witness_block(traces.cloned().collect())
+                    .unwrap_or_else(|e| panic!("ChunkBuilder: failed to build witness block: {e}"));
+                let rows = calculate_row_usage_of_witness_block(&witness_block)
+                    .unwrap_or_else(|e| panic!("ChunkBuilder: failed to calculate rows: {e}"));
+                acc_row_usage = RowUsage::from_row_usage_details(rows);
+            }
+            CCCMode::Siger | CCCMode::FollowerFull => {
+                let tx_by_tx = mode == CCCMode::Siger;
+                let mut checker = CircuitCapacityChecker::new();
+                if let Some(block_row_usage) = estimate_block(&mut checker, trace, tx_by_tx) {
+                    acc_row_usage.add(&block_row_usage);
+                }
+            }
+            CCCMode::Sequencer => {
+                let checker = self.checker.get_or_insert_with(CircuitCapacityChecker::new);
+                if let Some(row_usage) = estimate_block(checker, trace, true) {
+                    acc_row_usage = row_usage;
+                }
+            }
+        }
+        Some(acc_row_usage)
+    }
+
+    fn take_pending(&mut self, cut: ChunkCut) -> ProposedChunk {
+        let row_usage = std::mem::take(&mut self.acc_row_usage_normalized);
+        let traces = std::mem::take(&mut self.traces);
+        self.clear();
+        ProposedChunk {
+            traces,
+            cut,
+            row_usage,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.traces.clear();
+        self.usage = ChunkUsage::default();
+        self.acc_row_usage_normalized = RowUsage::default();
+        if let Some(checker) = &mut self.checker {
+            checker.reset();
+        }
+    }
+}
+
+/// Feeds the block to the checker, returning the normalized row usage accumulated by the
+/// checker. `None` if nothing is fed, i.e. the block has no tx and is checked tx by tx.
+fn estimate_block(
+    checker: &mut CircuitCapacityChecker,
+    trace: &BlockTrace,
+    tx_by_tx: bool,
+) -> Option<RowUsage> {
+    let estimate = |checker: &mut CircuitCapacityChecker, trace: BlockTrace| {
+        checker
+            .estimate_circuit_capacity(trace)
+            .unwrap_or_else(|e| panic!("ChunkBuilder: failed to estimate circuit capacity: {e}"))
+    };
+    if tx_by_tx {
+        split_block_trace_by_tx(trace)
+            .into_iter()
+            .map(|tx_trace| estimate(checker, tx_trace))
+            .last()
+    } else {
+        Some(estimate(checker, trace.clone()))
+    }
+}
+
+/// The sub-circuit with the most rows if the row usage is over capacity, or over `max_rows`.
+fn overflowed_circuit(row_usage: &RowUsage, max_rows: Option<usize>) -> Option<ChunkCut> {
+    let is_ok = max_rows.map_or(row_usage.is_ok, |max| row_usage.row_number <= max);
+    // Nothing is estimated yet, e.g. the chunk only has blocks without txs.
+    if is_ok || row_usage.row_usage_details.is_empty() {
+        return None;
+    }
+    row_usage
+        .row_usage_details
+        .iter()
+        .max_by_key(|r| r.row_number)
+        .map(|r| ChunkCut::CircuitCapacity(r.name.clone()))
+}
+
+/// Limits of a batch.
+#[derive(Debug, Clone)]
+pub struct BatchLimits {
+    /// At most `codec.max_chunks()`.
+    pub max_chunks: usize,
+    /// Max size of the blob in bytes.
+    pub max_blob_bytes: usize,
+    /// How the chunks are encoded into the blob.
+    pub codec: BatchCodec,
+}
+
+impl Default for BatchLimits {
+    fn default() -> Self {
+        Self {
+            max_chunks: MAX_AGG_SNARKS,
+            max_blob_bytes: N_BLOB_BYTES,
+            codec: BatchCodec::default(),
+        }
+    }
+}
+
+/// Why a batch ends where it does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BatchCut {
+    MaxChunks,
+    /// The next chunk would overflow the blob, compressed or not.
+    BlobOverflow,
+    /// The pending chunks are taken by `BatchBuilder::finish`.
+    Finish,
+}
+
+/// A batch built by `BatchBuilder`.
+#[derive(Debug)]
+pub struct ProposedBatch {
+    pub chunks: Vec<ChunkInfo>,
+    pub cut: BatchCut,
+}
+
+/// Builds batches from consecutive chunks.
+///
+/// A chunk which doesn't fit into the blob of the pending batch starts a new one, and a batch is
+/// complete once it has `max_chunks` chunks. A single chunk exceeding the blob makes a batch by
+/// itself.
+pub struct BatchBuilder {
+    limits: BatchLimits,
+    chunks: Vec<ChunkInfo>,
+}
+
+impl BatchBuilder {
+    pub fn new(limits: BatchLimits) -> Self {
+        let max_chunks = limits.codec.max_chunks();
+        assert!(
+            limits.max_chunks > 0 && limits.max_chunks <= max_chunks,
+            "max_chunks {} out of 1..={max_chunks} of {:?}",
+            limits.max_chunks,
+            limits.codec
+        );
+        Self {
+            limits,
+            chunks: Vec::new(),
+        }
+    }
+
+    /// Restores the pending batch, e.g. from a checkpoint of `pending`.
+    pub fn restore(&mut self, chunks: Vec<ChunkInfo>) {
+        self.chunks = chunks;
+    }
+
+    pub fn limits(&self) -> &BatchLimits {
+        &self.limits
+    }
+
+    /// Chunks of the pending batch.
+    pub fn pending(&self) -> &[ChunkInfo] {
+        &self.chunks
+    }
+
+    /// Adds the next chunk. Returns a batch if one is complete, which includes the chunk if the
+    /// chunk limit is reached and doesn't if the chunk exceeds the blob.
+    pub fn add(&mut self, chunk: ChunkInfo) -> Option<ProposedBatch> {
+        self.chunks.push(chunk);
+        log::debug!(
+            "BatchBuilder: checking chunk with len {}",
+            self.chunks.len()
+        );
+
+        // Condition0: chunk num
+        let condition0 = self.chunks.len() >= self.limits.max_chunks;
+        if condition0 {
+            return Some(self.take_pending(BatchCut::MaxChunks));
+        }
+
+        let uncompressed_da_size = self
+            .chunks
+            .iter()
+            .map(|chunk| chunk.tx_bytes.len() as u64)
+            .sum::<u64>();
+        let uncompressed_da_size_limit = self.limits.codec.max_chunk_bytes() as u64;
+        // Condition1: compressed bytes size, a blob the codec can't encode doesn't fit either
+        let condition1 = match blob_size(self.limits.codec, &self.chunks) {
+            Ok(compressed_da_size) => compressed_da_size >= self.limits.max_blob_bytes,
+            Err(e) => {
+                log::warn!("BatchBuilder: {e:#}");
+                true
+            }
+        };
+        // Condition2: uncompressed bytes size
+        let condition2 = uncompressed_da_size > uncompressed_da_size_limit;
+
+        let overflow = condition1 || condition2;
+        if !overflow {
+            return None;
+        }
+        if self.chunks.len() == 1 {
+            log::warn!("BatchBuilder: a single chunk exceeds the blob");
+            return Some(self.take_pending(BatchCut::BlobOverflow));
+        }
+        // pop the last chunk and emit prev chunks
+        let chunk = self.chunks.pop().unwrap();
+        let batch = self.take_pending(BatchCut::BlobOverflow);
+        self.chunks.push(chunk);
+
+        Some(batch)
+    }
+
+    /// Takes the pending chunks as the last batch, e.g. at the end of a block range.
+    pub fn finish(&mut self) -> Option<ProposedBatch> {
+        (!self.chunks.is_empty()).then(|| self.take_pending(BatchCut::Finish))
+    }
+
+    /// Drops the pending chunks.
+    pub fn reset(&mut self) {
+        self.chunks.clear();
+    }
+
+    fn take_pending(&mut self, cut: BatchCut) -> ProposedBatch {
+        ProposedBatch {
+            chunks: std::mem::take(&mut self.chunks),
+            cut,
+        }
+    }
+}
+
+/// Size of the blob of a batch of the chunks in the codec, in bytes.
+pub fn blob_size(codec: BatchCodec, chunks: &[ChunkInfo]) -> Result<usize> {
+    Ok(codec.blob_bytes(chunks)?.len())
+}
+
+/// Length and cut of the first chunk built from `traces` by a new `ChunkBuilder`, with `next`
+/// following them. `None` if no chunk is cut, e.g. `traces` are too few to fill a chunk.
+///
+/// Used to check a chunk built elsewhere, which matches if the length is that of `traces`.
+pub fn first_chunk_cut(
+    limits: &ChunkLimits,
+    traces: &[BlockTrace],
+    next: Option<&BlockTrace>,
+) -> Option<(usize, ChunkCut)> {
+    let mut chunk_builder = ChunkBuilder::new(limits.clone());
+    traces
+        .iter()
+        .chain(next)
+        .find_map(|trace| chunk_builder.add(trace.clone()))
+        .map(|chunk| (chunk.traces.len(), chunk.cut))
+}
+
+/// Same with `first_chunk_cut`, for the first batch built from `chunks`.
+pub fn first_batch_cut(
+    limits: &BatchLimits,
+    chunks: &[ChunkInfo],
+    next: Option<&ChunkInfo>,
+) -> Option<(usize, BatchCut)> {
+    let mut batch_builder = BatchBuilder::new(limits.clone());
+    chunks
+        .iter()
+        .chain(next)
+        .find_map(|chunk| batch_builder.add(chunk.clone()))
+        .map(|batch| (batch.chunks.len(), batch.cut))
+}
+
+/// `ChunkInfo` of the chunk built straight from its block traces, same with
+/// `ChunkInfo::from_witness_block` but without building the witness block.
+///
+/// With `CHUNK_INFO_VERIFY=true` it is cross-checked against `ChunkInfo::from_witness_block`.
+/// That is not tied to debug assertions, which are enabled in the release profile too.
+pub fn chunk_info_from_traces(traces: &[BlockTrace]) -> ChunkInfo {
+    let first = traces.first().expect("chunk_info_from_traces: empty chunk");
+    let last = traces.last().unwrap();
+
+    // Block contexts, then hashes of all txs, with L1 messages counted by queue index so that
+    // skipped messages are included.
+    let mut total_l1_popped = first.start_l1_queue_index;
+    let mut data_bytes = vec![];
+    for trace in traces {
+        let header = &trace.header;
+        let num_l2_txs = trace
+            .transactions
+            .iter()
+            .filter(|tx| !tx.is_l1_tx())
+            .count() as u64;
+        let num_l1_msgs = trace
+            .transactions
+            .iter()
+            .filter(|tx| tx.is_l1_tx())
+            .map(|tx| tx.nonce)
+            .max()
+            .map_or(0, |max_queue_index| max_queue_index - total_l1_popped + 1);
+        total_l1_popped += num_l1_msgs;
+
+        let mut base_fee = [0u8; 32];
+        header
+            .base_fee_per_gas
+            .unwrap_or_default()
+            .to_big_endian(&mut base_fee);
+        data_bytes.extend(header.number.unwrap().as_u64().to_be_bytes());
+        data_bytes.extend(header.timestamp.as_u64().to_be_bytes());
+        data_bytes.extend(base_fee);
+        data_bytes.extend(header.gas_limit.as_u64().to_be_bytes());
+        data_bytes.extend(((num_l2_txs + num_l1_msgs) as u16).to_be_bytes());
+    }
+    for trace in traces {
+        data_bytes.extend(trace.transactions.iter().flat_map(|tx| tx.tx_hash.0));
+    }
+
+    let tx_bytes = traces
+        .iter()
+        .flat_map(|trace| {
+            trace
+                .transactions
+                .iter()
+                .filter(|tx| !tx.is_l1_tx())
+                .flat_map(|tx| {
+                    tx.to_eth_tx(
+                        trace.header.hash,
+                        trace.header.number,
+                        None,
+                        trace.header.base_fee_per_gas,
+                    )
+                    .rlp()
+                    .to_vec()
+                })
+        })
+        .collect();
+
+    let chunk_info = ChunkInfo {
+        chain_id: last.chain_id,
+        prev_state_root: first.storage_trace.root_before,
+        post_state_root: last.header.state_root,
+        withdraw_root: last.withdraw_trie_root,
+        data_hash: H256(keccak256(data_bytes)),
+        tx_bytes,
+        is_padding: false,
+    };
+
+    if read_env_var("CHUNK_INFO_VERIFY", false) {
+        let witness_block = chunk_trace_to_witness_block(traces.to_vec()).unwrap_or_else(|e| {
+            panic!("chunk_info_from_traces: failed to build witness block: {e}")
+        });
+        assert_eq!(
+            chunk_info,
+            ChunkInfo::from_witness_block(&witness_block, false),
+            "chunk_info_from_traces: differs from the witness block one"
+        );
+    }
+    chunk_info
+}
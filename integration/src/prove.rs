@@ -1,11 +1,11 @@
 use halo2_proofs::{halo2curves::bn256::Bn256, poly::kzg::commitment::ParamsKZG};
 use prover::{
-    get_blob_bytes, BatchData, BatchProofV2, BatchProver, BatchProvingTask, BatchVerifier,
-    BundleProvingTask, ChunkInfo, ChunkProver, ChunkProvingTask, ChunkVerifier, MAX_AGG_SNARKS,
+    BatchProofV2, BatchProver, BatchProvingTask, BatchVerifier, BundleProvingTask, ChunkInfo,
+    ChunkProver, ChunkProvingTask, ChunkVerifier,
 };
 use std::{collections::BTreeMap, env, time::Instant};
 
-use crate::verifier::EVMVerifier;
+use crate::{codec::BatchCodec, verifier::EVMVerifier};
 
 /// The `output_dir` is assumed to output_dir of chunk proving.
 pub fn new_batch_prover<'a>(
@@ -106,22 +106,9 @@ pub fn prove_and_verify_bundle(
     log::info!("Prove bundle END");
 }
 
-// `chunks` are unpadded
-// Similar codes with aggregator/src/tests/aggregation.rs
-// Refactor?
-pub fn get_blob_from_chunks(chunks: &[ChunkInfo]) -> Vec<u8> {
-    let num_chunks = chunks.len();
-
-    let padded_chunk =
-        ChunkInfo::mock_padded_chunk_info_for_testing(chunks.last().as_ref().unwrap());
-    let chunks_with_padding = [
-        chunks.to_vec(),
-        vec![padded_chunk; MAX_AGG_SNARKS - num_chunks],
-    ]
-    .concat();
-    let batch_data = BatchData::<{ MAX_AGG_SNARKS }>::new(chunks.len(), &chunks_with_padding);
-    let batch_bytes = batch_data.get_batch_data_bytes();
-    let blob_bytes = get_blob_bytes(&batch_bytes);
-    log::info!("blob_bytes len {}", blob_bytes.len());
-    blob_bytes
+/// Blob bytes of the batch of `chunks` in the codec. `chunks` are unpadded.
+pub fn get_blob_from_chunks(codec: BatchCodec, chunks: &[ChunkInfo]) -> anyhow::Result<Vec<u8>> {
+    let blob_bytes = codec.blob_bytes(chunks)?;
+    log::info!("blob_bytes len {} of {codec:?}", blob_bytes.len());
+    Ok(blob_bytes)
 }
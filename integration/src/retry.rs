@@ -0,0 +1,49 @@
+use anyhow::Result;
+use std::{future::Future, time::Duration};
+
+#[derive(Debug, Clone, Copy)]
+pub struct RetryConfig {
+    /// Max number of retries after the first attempt.
+    pub max_retries: u32,
+    /// Backoff before the first retry, doubled for every following retry.
+    pub initial_backoff: Duration,
+    /// Upper bound of the backoff.
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryConfig {
+    /// No retries.
+    fn default() -> Self {
+        Self {
+            max_retries: 0,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Runs `f` until it succeeds or `config.max_retries` retries are used up, sleeping with
+/// exponential backoff in between.
+pub async fn retry<T, F, Fut>(config: &RetryConfig, id: &str, mut f: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut backoff = config.initial_backoff;
+    let mut retries = 0;
+    loop {
+        match f().await {
+            Ok(res) => return Ok(res),
+            Err(e) if retries < config.max_retries => {
+                retries += 1;
+                log::warn!(
+                    "{id}: request failed, retry {retries}/{} in {backoff:?}: {e}",
+                    config.max_retries
+                );
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(config.max_backoff);
+            }
+            Err(e) => return Err(e.context(format!("{id}: gave up after {retries} retries"))),
+        }
+    }
+}
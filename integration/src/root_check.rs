@@ -0,0 +1,192 @@
+//! Continuity of state roots, withdraw roots and chain ids over blocks or chunks, which the batch
+//! circuit requires of consecutive chunks.
+
+use crate::proposer::chunk_info_from_traces;
+use prover::{
+    eth_types::{l2_types::BlockTrace, H256},
+    ChunkInfo, ChunkProofV2,
+};
+use std::fmt;
+
+/// Roots of a block or chunk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Roots {
+    pub chain_id: u64,
+    pub prev_state_root: H256,
+    pub post_state_root: H256,
+    pub withdraw_root: H256,
+    /// First and last block, if known.
+    pub blocks: Option<(u64, u64)>,
+}
+
+impl Roots {
+    pub fn from_block(trace: &BlockTrace) -> Self {
+        let block_num = trace.header.number.map_or(0, |n| n.as_u64());
+        Self {
+            chain_id: trace.chain_id,
+            prev_state_root: trace.storage_trace.root_before,
+            post_state_root: trace.header.state_root,
+            withdraw_root: trace.withdraw_trie_root,
+            blocks: Some((block_num, block_num)),
+        }
+    }
+
+    /// Roots of the chunk of `traces`, see `chunk_info_from_traces`.
+    pub fn from_chunk_traces(traces: &[BlockTrace]) -> Self {
+        let block_num = |trace: &BlockTrace| trace.header.number.map_or(0, |n| n.as_u64());
+        Self {
+            blocks: Some((
+                block_num(traces.first().unwrap()),
+                block_num(traces.last().unwrap()),
+            )),
+            ..Self::from_chunk_info(&chunk_info_from_traces(traces))
+        }
+    }
+
+    pub fn from_chunk_info(chunk: &ChunkInfo) -> Self {
+        Self {
+            chain_id: chunk.chain_id,
+            prev_state_root: chunk.prev_state_root,
+            post_state_root: chunk.post_state_root,
+            withdraw_root: chunk.withdraw_root,
+            blocks: None,
+        }
+    }
+
+    pub fn from_chunk_proof(proof: &ChunkProofV2) -> Self {
+        Self::from_chunk_info(proof.inner.chunk_info())
+    }
+
+    fn first_block(&self) -> Option<u64> {
+        self.blocks.map(|(first, _)| first)
+    }
+
+    // The mismatch of block ranges if `next` doesn't follow right after, which is assumed if
+    // block ranges are unknown.
+    fn followed_by(&self, next: &Self) -> Option<RootMismatch> {
+        match (self.blocks, next.blocks) {
+            (Some((_, last)), Some((next_first, _))) if last + 1 != next_first => {
+                Some(RootMismatch::BlockRange { last, next_first })
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RootMismatch {
+    /// The next one doesn't start right after the last block, i.e. is out of order or after a
+    /// gap. Roots are not compared then.
+    BlockRange {
+        last: u64,
+        next_first: u64,
+    },
+    /// The post state root isn't the prev state root of the next one.
+    StateRoot {
+        post: H256,
+        next_prev: H256,
+    },
+    /// The withdraw root changes while the state root doesn't.
+    WithdrawRoot {
+        withdraw_root: H256,
+        next: H256,
+    },
+    ChainId {
+        chain_id: u64,
+        next: u64,
+    },
+}
+
+/// Mismatch between consecutive blocks or chunks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RootViolation {
+    /// Index of the block or chunk following the mismatch.
+    pub index: usize,
+    /// First block following the mismatch, if known.
+    pub block_num: Option<u64>,
+    pub mismatch: RootMismatch,
+}
+
+impl fmt::Display for RootViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.block_num {
+            Some(block_num) => write!(f, "before block-{block_num}: ")?,
+            None => write!(f, "before #{}: ", self.index)?,
+        }
+        match &self.mismatch {
+            RootMismatch::BlockRange { last, next_first } => {
+                write!(f, "last block-{last}, next first block-{next_first}")
+            }
+            RootMismatch::StateRoot { post, next_prev } => {
+                write!(
+                    f,
+                    "post state root {post:?}, next prev state root {next_prev:?}"
+                )
+            }
+            RootMismatch::WithdrawRoot {
+                withdraw_root,
+                next,
+            } => write!(
+                f,
+                "withdraw root {withdraw_root:?} changes to {next:?} without state change"
+            ),
+            RootMismatch::ChainId { chain_id, next } => {
+                write!(f, "chain id {chain_id}, next chain id {next}")
+            }
+        }
+    }
+}
+
+/// Checks that consecutive blocks or chunks follow each other by block range, chain by state
+/// root, keep the withdraw root without a state change, and share the chain id. Separate block
+/// ranges should be checked one by one.
+pub fn check_roots(items: &[Roots]) -> Vec<RootViolation> {
+    let mut violations = vec![];
+    for (index, (prev, next)) in items.iter().zip(items.iter().skip(1)).enumerate() {
+        let mut violation = |mismatch| {
+            violations.push(RootViolation {
+                index: index + 1,
+                block_num: next.first_block(),
+                mismatch,
+            })
+        };
+        if let Some(mismatch) = prev.followed_by(next) {
+            violation(mismatch);
+            continue;
+        }
+        if prev.chain_id != next.chain_id {
+            violation(RootMismatch::ChainId {
+                chain_id: prev.chain_id,
+                next: next.chain_id,
+            });
+        }
+        if prev.post_state_root != next.prev_state_root {
+            violation(RootMismatch::StateRoot {
+                post: prev.post_state_root,
+                next_prev: next.prev_state_root,
+            });
+        }
+        if next.prev_state_root == next.post_state_root && prev.withdraw_root != next.withdraw_root
+        {
+            violation(RootMismatch::WithdrawRoot {
+                withdraw_root: prev.withdraw_root,
+                next: next.withdraw_root,
+            });
+        }
+    }
+    violations
+}
+
+/// Checks the blocks of the traces, which covers the chunks of the traces as well.
+pub fn check_block_roots<'a>(
+    traces: impl IntoIterator<Item = &'a BlockTrace>,
+) -> Vec<RootViolation> {
+    let roots: Vec<_> = traces.into_iter().map(Roots::from_block).collect();
+    check_roots(&roots)
+}
+
+/// Checks the chunks of the proofs, e.g. of a batch.
+pub fn check_chunk_proof_roots(proofs: &[ChunkProofV2]) -> Vec<RootViolation> {
+    let roots: Vec<_> = proofs.iter().map(Roots::from_chunk_proof).collect();
+    check_roots(&roots)
+}
@@ -3,7 +3,9 @@ use std::{
     path::{Path, PathBuf},
 };
 
+use crate::{l1_queue::check_l1_queue, trace_check::check_traces};
 use glob::glob;
+use itertools::Itertools;
 use prover::{eth_types::l2_types::BlockTrace, get_block_trace_from_file, read_env_var};
 
 pub const ASSETS_DIR: &str = "./test_assets";
@@ -55,6 +57,43 @@ pub fn load_chunk(trace_path: &str) -> (Vec<String>, Vec<BlockTrace>) {
     (paths, traces)
 }
 
+/// Like `load_chunk`, panicking if the traces break the L1 message queue or fail the sanity
+/// checks, e.g. before proving them.
+pub fn load_chunk_checked(trace_path: &str) -> (Vec<String>, Vec<BlockTrace>) {
+    let (paths, traces) = load_chunk(trace_path);
+    assert_l1_queue(&traces, None);
+    assert_traces(&traces);
+    (paths, traces)
+}
+
+/// Panics if the traces fail the sanity checks, see `check_traces`.
+pub fn assert_traces<'a>(traces: impl IntoIterator<Item = &'a BlockTrace>) {
+    let violations = check_traces(traces, None);
+    if !violations.is_empty() {
+        panic!("trace violations: {}", violations.iter().join("; "));
+    }
+}
+
+/// Panics if the L1 messages of the traces are not contiguous, see `check_l1_queue`. Skipped
+/// messages are only logged. Returns the next queue index, to check the next chunk with.
+pub fn assert_l1_queue<'a>(
+    traces: impl IntoIterator<Item = &'a BlockTrace>,
+    expected_start: Option<u64>,
+) -> u64 {
+    match check_l1_queue(traces, expected_start) {
+        Ok((next, skipped)) => {
+            for skipped in skipped {
+                log::warn!("L1 message queue: {skipped}");
+            }
+            next
+        }
+        Err(violations) => panic!(
+            "L1 message queue violations: {}",
+            violations.iter().join("; ")
+        ),
+    }
+}
+
 pub fn load_batch(batch_dir: &str) -> anyhow::Result<Vec<String>> {
     let sorted_dirs = read_dir_recursive(batch_dir, "chunk_")?;
     log::info!("batch content: {:?}", sorted_dirs);
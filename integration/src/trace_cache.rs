@@ -0,0 +1,197 @@
+//! On-disk cache of block traces, laid out as `{dir}/{chain_id}/{options}/block_N.json` so that
+//! each `{options}` dir can be read by `test_util::load_chunk`. Tx-by-tx traces are kept in the
+//! `txbytx` subdir, which `load_chunk` doesn't read.
+
+use crate::trace_check::check_traces;
+use anyhow::{anyhow, bail, Context, Result};
+use itertools::Itertools;
+use prover::eth_types::l2_types::BlockTrace;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CacheMode {
+    /// Serve cached traces, fetch and cache the missing ones.
+    #[default]
+    ReadThrough,
+    /// Always fetch, and cache the traces not cached yet.
+    WriteThrough,
+    /// Serve cached traces only, fail on missing ones without fetching.
+    CacheOnly,
+    /// Always fetch, and overwrite the cached traces.
+    Refresh,
+}
+
+impl FromStr for CacheMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "read_through" => Ok(Self::ReadThrough),
+            "write_through" => Ok(Self::WriteThrough),
+            "cache_only" => Ok(Self::CacheOnly),
+            "refresh" => Ok(Self::Refresh),
+            _ => Err(format!("unknown trace cache mode {s}")),
+        }
+    }
+}
+
+impl CacheMode {
+    /// Whether cached traces are served.
+    pub fn reads(&self) -> bool {
+        matches!(self, Self::ReadThrough | Self::CacheOnly)
+    }
+
+    /// Whether missing traces are fetched.
+    pub fn fetches(&self) -> bool {
+        !matches!(self, Self::CacheOnly)
+    }
+}
+
+/// Kind of the cached traces of a block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceKind {
+    Block,
+    TxByTx,
+}
+
+pub struct TraceCache {
+    dir: PathBuf,
+    mode: CacheMode,
+}
+
+impl TraceCache {
+    pub fn new(dir: impl Into<PathBuf>, mode: CacheMode) -> Self {
+        Self {
+            dir: dir.into(),
+            mode,
+        }
+    }
+
+    pub fn mode(&self) -> CacheMode {
+        self.mode
+    }
+
+    /// Dir of the cached block traces of the chain under the trace options, which
+    /// `test_util::load_chunk` can read.
+    pub fn block_dir(&self, chain_id: u64, options: &str) -> PathBuf {
+        self.dir.join(chain_id.to_string()).join(options)
+    }
+
+    pub fn path(&self, chain_id: u64, options: &str, kind: TraceKind, block_num: u64) -> PathBuf {
+        let dir = self.block_dir(chain_id, options);
+        let dir = match kind {
+            TraceKind::Block => dir,
+            TraceKind::TxByTx => dir.join("txbytx"),
+        };
+        dir.join(format!("block_{block_num}.json"))
+    }
+
+    /// Reads the cached traces, `None` if not cached. Fails if the file is broken, e.g.
+    /// truncated by an interrupted write.
+    pub fn read<T: CachedTrace>(&self, path: &Path, block_num: u64) -> Result<Option<T>> {
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!("failed to read {}", path.display())),
+        };
+        let trace = check_trace(&bytes, block_num)
+            .with_context(|| format!("broken trace cache file {}", path.display()))?;
+        Ok(Some(trace))
+    }
+
+    /// Writes the traces to a temp file first, so that an interrupted write leaves no
+    /// truncated cache file behind.
+    pub fn write<T: CachedTrace>(&self, path: &Path, trace: &T) -> Result<()> {
+        let dir = path.parent().unwrap();
+        fs::create_dir_all(dir)?;
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, serde_json::to_vec(trace)?)?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    /// Checks every cached file, returning the broken ones with the reasons.
+    pub fn check_integrity(&self) -> Result<Vec<(PathBuf, String)>> {
+        let mut broken = vec![];
+        for entry in glob::glob(&format!("{}/*/*/**/*.json", self.dir.display()))? {
+            let path = entry?;
+            let Some(block_num) = block_num_of_path(&path) else {
+                broken.push((path, "not named block_N.json".to_string()));
+                continue;
+            };
+            let is_txbytx = path.parent().is_some_and(|dir| dir.ends_with("txbytx"));
+            let bytes = fs::read(&path)?;
+            let checked = if is_txbytx {
+                check_trace::<Vec<BlockTrace>>(&bytes, block_num).map(|_| ())
+            } else {
+                check_trace::<BlockTrace>(&bytes, block_num).map(|_| ())
+            };
+            if let Err(e) = checked {
+                broken.push((path, format!("{e:#}")));
+            }
+        }
+        Ok(broken)
+    }
+}
+
+/// Traces cached per block.
+pub trait CachedTrace: Serialize + DeserializeOwned {
+    /// Block numbers of the traces, which must all be the cached block.
+    fn block_nums(&self) -> Vec<Option<u64>>;
+
+    /// Sanity violations of the traces, see `trace_check`.
+    fn violations(&self) -> Vec<String> {
+        vec![]
+    }
+}
+
+impl CachedTrace for BlockTrace {
+    fn block_nums(&self) -> Vec<Option<u64>> {
+        vec![self.header.number.map(|n| n.as_u64())]
+    }
+
+    fn violations(&self) -> Vec<String> {
+        check_traces([self], None)
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+}
+
+impl CachedTrace for Vec<BlockTrace> {
+    fn block_nums(&self) -> Vec<Option<u64>> {
+        self.iter().flat_map(CachedTrace::block_nums).collect()
+    }
+}
+
+fn check_trace<T: CachedTrace>(bytes: &[u8], block_num: u64) -> Result<T> {
+    if bytes.is_empty() {
+        bail!("empty file");
+    }
+    let trace: T = serde_json::from_slice(bytes).map_err(|e| anyhow!("invalid json: {e}"))?;
+    if let Some(other) = trace
+        .block_nums()
+        .into_iter()
+        .find(|num| *num != Some(block_num))
+    {
+        bail!("trace of block {other:?} instead of block {block_num}");
+    }
+    let violations = trace.violations();
+    if !violations.is_empty() {
+        bail!("invalid trace: {}", violations.iter().join("; "));
+    }
+    Ok(trace)
+}
+
+fn block_num_of_path(path: &Path) -> Option<u64> {
+    path.file_stem()?
+        .to_str()?
+        .strip_prefix("block_")?
+        .parse()
+        .ok()
+}
@@ -0,0 +1,195 @@
+//! Sanity checks of block traces as fetched from l2geth or loaded from files, which catch bad or
+//! incomplete traces before they fail witness generation or proving.
+
+use anyhow::{anyhow, Result};
+use ethers_core::utils::keccak256;
+use itertools::Itertools;
+use prover::eth_types::{l2_types::BlockTrace, H256};
+use std::{collections::HashSet, fmt};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TraceViolation {
+    /// The trace is of another block than the requested one.
+    BlockNumber { expected: u64, number: Option<u64> },
+    /// The block doesn't follow the previous one.
+    BlockGap { block_num: u64, prev_block_num: u64 },
+    /// The parent hash isn't the hash of the previous block.
+    ParentHash {
+        block_num: u64,
+        parent_hash: H256,
+        prev_hash: H256,
+    },
+    ChainId {
+        block_num: u64,
+        chain_id: u64,
+        expected: u64,
+    },
+    /// Per-tx traces, i.e. `tx_storage_trace` or `execution_results`, not one per tx.
+    TxTraces {
+        block_num: u64,
+        field: &'static str,
+        len: usize,
+        num_txs: usize,
+    },
+    /// The bytecode of the account called by the tx is not in `codes`.
+    MissingCode {
+        block_num: u64,
+        tx_index: usize,
+        code_hash: H256,
+    },
+}
+
+impl fmt::Display for TraceViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BlockNumber { expected, number } => {
+                write!(f, "trace of block {number:?} instead of block-{expected}")
+            }
+            Self::BlockGap {
+                block_num,
+                prev_block_num,
+            } => write!(f, "block-{block_num} follows block-{prev_block_num}"),
+            Self::ParentHash {
+                block_num,
+                parent_hash,
+                prev_hash,
+            } => write!(
+                f,
+                "block-{block_num} parent hash {parent_hash:?}, previous block hash {prev_hash:?}"
+            ),
+            Self::ChainId {
+                block_num,
+                chain_id,
+                expected,
+            } => write!(
+                f,
+                "block-{block_num} chain id {chain_id}, expected {expected}"
+            ),
+            Self::TxTraces {
+                block_num,
+                field,
+                len,
+                num_txs,
+            } => write!(f, "block-{block_num} has {len} {field} for {num_txs} txs"),
+            Self::MissingCode {
+                block_num,
+                tx_index,
+                code_hash,
+            } => write!(
+                f,
+                "block-{block_num} tx-{tx_index} calls code {code_hash:?} not in codes"
+            ),
+        }
+    }
+}
+
+/// Checks the trace requested for `block_num`, see `check_traces`.
+pub fn check_block_trace(
+    trace: &BlockTrace,
+    block_num: u64,
+    chain_id: Option<u64>,
+) -> Vec<TraceViolation> {
+    let number = trace.header.number.map(|n| n.as_u64());
+    let mut violations = vec![];
+    if number != Some(block_num) {
+        violations.push(TraceViolation::BlockNumber {
+            expected: block_num,
+            number,
+        });
+    }
+    violations.extend(check_traces([trace], chain_id));
+    violations
+}
+
+/// Like `check_block_trace`, failing with the violations if any.
+pub fn ensure_block_trace(trace: &BlockTrace, block_num: u64, chain_id: Option<u64>) -> Result<()> {
+    let violations = check_block_trace(trace, block_num, chain_id);
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!("invalid trace: {}", violations.iter().join("; ")))
+    }
+}
+
+/// Checks the traces, e.g. of a chunk, each on its own and against each other:
+/// - blocks follow the previous one, and link to it by parent hash.
+/// - all blocks are of `chain_id`, or of the chain of the first block if not set.
+/// - `tx_storage_trace` and `execution_results` have one trace per tx.
+/// - the bytecodes called by the txs are in `codes`. Traces without `codes` are of the legacy
+///   format, which carries the bytecodes in the execution results, and are not checked.
+pub fn check_traces<'a>(
+    traces: impl IntoIterator<Item = &'a BlockTrace>,
+    chain_id: Option<u64>,
+) -> Vec<TraceViolation> {
+    let mut violations = vec![];
+    let mut chain_id = chain_id;
+    let mut prev: Option<(u64, Option<H256>)> = None;
+    for trace in traces {
+        let block_num = trace.header.number.map_or(0, |n| n.as_u64());
+
+        match prev {
+            Some((prev_block_num, _)) if prev_block_num + 1 != block_num => {
+                violations.push(TraceViolation::BlockGap {
+                    block_num,
+                    prev_block_num,
+                })
+            }
+            Some((_, Some(prev_hash))) if trace.header.parent_hash != prev_hash => {
+                violations.push(TraceViolation::ParentHash {
+                    block_num,
+                    parent_hash: trace.header.parent_hash,
+                    prev_hash,
+                })
+            }
+            _ => {}
+        }
+        prev = Some((block_num, trace.header.hash));
+
+        let expected = *chain_id.get_or_insert(trace.chain_id);
+        if trace.chain_id != expected {
+            violations.push(TraceViolation::ChainId {
+                block_num,
+                chain_id: trace.chain_id,
+                expected,
+            });
+        }
+
+        let num_txs = trace.transactions.len();
+        for (field, len) in [
+            ("tx_storage_trace", trace.tx_storage_trace.len()),
+            ("execution_results", trace.execution_results.len()),
+        ] {
+            if len != num_txs {
+                violations.push(TraceViolation::TxTraces {
+                    block_num,
+                    field,
+                    len,
+                    num_txs,
+                });
+            }
+        }
+
+        if trace.codes.is_empty() {
+            continue;
+        }
+        let code_hashes: HashSet<H256> = trace
+            .codes
+            .iter()
+            .map(|code| H256(keccak256(&code.code)))
+            .collect();
+        for (tx_index, result) in trace.execution_results.iter().enumerate() {
+            // EOAs and contract creations call no code.
+            match &result.to {
+                Some(to) if to.code_size > 0 && !code_hashes.contains(&to.keccak_code_hash) => {
+                    violations.push(TraceViolation::MissingCode {
+                        block_num,
+                        tx_index,
+                        code_hash: to.keccak_code_hash,
+                    })
+                }
+                _ => {}
+            }
+        }
+    }
+    violations
+}
@@ -0,0 +1,180 @@
+//! Options of the l2geth tracer, i.e. the config param of `scroll_getBlockTraceByNumberOrHash`
+//! and `scroll_getTxByTxBlockTrace`.
+
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StorageProofFormat {
+    #[default]
+    Legacy,
+    Flatten,
+}
+
+impl StorageProofFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Legacy => "legacy",
+            Self::Flatten => "flatten",
+        }
+    }
+}
+
+impl FromStr for StorageProofFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "legacy" => Ok(Self::Legacy),
+            "flatten" => Ok(Self::Flatten),
+            _ => Err(format!("unknown storage proof format {s}")),
+        }
+    }
+}
+
+/// Hardforks whose activation can be overridden when tracing, in activation order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Hardfork {
+    Curie,
+    Darwin,
+    DarwinV2,
+    Euclid,
+    EuclidV2,
+    Feynman,
+}
+
+impl Hardfork {
+    pub const ALL: [Self; 6] = [
+        Self::Curie,
+        Self::Darwin,
+        Self::DarwinV2,
+        Self::Euclid,
+        Self::EuclidV2,
+        Self::Feynman,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Curie => "curie",
+            Self::Darwin => "darwin",
+            Self::DarwinV2 => "darwin_v2",
+            Self::Euclid => "euclid",
+            Self::EuclidV2 => "euclid_v2",
+            Self::Feynman => "feynman",
+        }
+    }
+
+    /// Field of the l2geth chain config, a block number for curie and a timestamp for the later
+    /// hardforks.
+    pub fn config_key(&self) -> &'static str {
+        match self {
+            Self::Curie => "curieBlock",
+            Self::Darwin => "darwinTime",
+            Self::DarwinV2 => "darwinV2Time",
+            Self::Euclid => "euclidTime",
+            Self::EuclidV2 => "euclidV2Time",
+            Self::Feynman => "feynmanTime",
+        }
+    }
+}
+
+impl FromStr for Hardfork {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "curie" => Ok(Self::Curie),
+            "darwin" => Ok(Self::Darwin),
+            "darwin_v2" => Ok(Self::DarwinV2),
+            "euclid" => Ok(Self::Euclid),
+            "euclid_v2" => Ok(Self::EuclidV2),
+            "feynman" => Ok(Self::Feynman),
+            _ => Err(format!("unknown hardfork {s}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TraceOptions {
+    pub storage_proof_format: StorageProofFormat,
+    /// Activation block or time of the hardforks, overriding the chain config of l2geth.
+    pub overrides: BTreeMap<Hardfork, u64>,
+    /// Other fields of the tracer config, passed as they are.
+    pub extra: Map<String, Value>,
+}
+
+impl TraceOptions {
+    /// Traces under the hardfork activated from the start of the chain, e.g. to re-trace history
+    /// under an upcoming hardfork. Earlier hardforks are activated as well.
+    pub fn with_hardfork(mut self, hardfork: Hardfork) -> Self {
+        for fork in Hardfork::ALL {
+            if fork <= hardfork {
+                // Block 0 is the genesis block, whose trace isn't requested.
+                let activation = if fork == Hardfork::Curie { 1 } else { 0 };
+                self.overrides.entry(fork).or_insert(activation);
+            }
+        }
+        self
+    }
+
+    /// Parses `overrides` of the form `curie=1,darwin=0`.
+    pub fn parse_overrides(overrides: &str) -> Result<BTreeMap<Hardfork, u64>, String> {
+        overrides
+            .split(',')
+            .filter(|s| !s.trim().is_empty())
+            .map(|s| {
+                let (fork, activation) = s
+                    .split_once('=')
+                    .ok_or_else(|| format!("invalid hardfork override {s}"))?;
+                let activation = activation
+                    .trim()
+                    .parse()
+                    .map_err(|e| format!("invalid hardfork override {s}: {e}"))?;
+                Ok((fork.trim().parse()?, activation))
+            })
+            .collect()
+    }
+
+    /// Tracer config param of the trace APIs. The default storage proof format is left out with
+    /// overrides, as the former curie override sent only the override, to older l2geth as well.
+    pub fn to_param(&self) -> Value {
+        let mut param = self.extra.clone();
+        if self.storage_proof_format != StorageProofFormat::default() || self.overrides.is_empty() {
+            param.insert(
+                "StorageProofFormat".to_string(),
+                self.storage_proof_format.as_str().into(),
+            );
+        }
+        if !self.overrides.is_empty() {
+            let overrides: Map<String, Value> = self
+                .overrides
+                .iter()
+                .map(|(fork, activation)| (fork.config_key().to_string(), (*activation).into()))
+                .collect();
+            param.insert("overrides".to_string(), overrides.into());
+        }
+        param.into()
+    }
+
+    /// Key of the traces in the trace cache, distinct for options giving distinct traces, e.g.
+    /// `legacy` or `legacy_curie-1`.
+    pub fn cache_key(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl fmt::Display for TraceOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.storage_proof_format.as_str())?;
+        for (fork, activation) in &self.overrides {
+            write!(f, "_{}-{activation}", fork.name())?;
+        }
+        if !self.extra.is_empty() {
+            let digest = Sha256::digest(Value::from(self.extra.clone()).to_string());
+            let digest: String = digest[..4].iter().map(|b| format!("{b:02x}")).collect();
+            write!(f, "_x{digest}")?;
+        }
+        Ok(())
+    }
+}
@@ -1,4 +1,7 @@
-use integration::prove::{new_batch_prover, prove_and_verify_batch};
+use integration::{
+    codec::BatchCodec,
+    prove::{new_batch_prover, prove_and_verify_batch},
+};
 use prover::{init_env_and_log, read_json_deep, BatchProvingTask};
 use std::{fs, path::PathBuf};
 
@@ -17,9 +20,16 @@ fn test_batch_prove_verify() {
         &BATCH_PROVER_DEGREES.iter().copied().collect_vec(),
     );
 
-    //let task_path = "tests/test_data/batch-task-with-blob.json"; // zstd
-    let task_path = "tests/test_data/batch-task-with-blob-raw.json"; // no zstd
+    let codec: BatchCodec = prover::read_env_var("BATCH_CODEC", "v4-raw".to_string())
+        .parse()
+        .unwrap();
+    let task_path = match codec {
+        BatchCodec::V4 { compressed: true } => "tests/test_data/batch-task-with-blob.json",
+        BatchCodec::V4 { compressed: false } => "tests/test_data/batch-task-with-blob-raw.json",
+        _ => panic!("no batch task with blob of {codec:?}"),
+    };
     let mut batch = load_batch_proving_task(task_path);
+    assert_eq!(batch.batch_header.version, codec.version());
     log::info!("batch hash = {:?}", batch.batch_header.batch_hash());
 
     let chunk_infos = batch
@@ -6,7 +6,7 @@ use prover::init_env_and_log;
 fn test_chunk_prove_verify() {
     use integration::{
         prove::prove_and_verify_chunk,
-        test_util::{load_chunk, trace_path_for_test},
+        test_util::{load_chunk_checked, trace_path_for_test},
     };
     use itertools::Itertools;
     use prover::{ChunkProvingTask, CHUNK_PROVER_DEGREES};
@@ -20,7 +20,7 @@ fn test_chunk_prove_verify() {
     );
 
     let trace_path = trace_path_for_test();
-    let traces = load_chunk(&trace_path).1;
+    let traces = load_chunk_checked(&trace_path).1;
     let chunk = ChunkProvingTask::new(traces);
     prove_and_verify_chunk(chunk, None, &params_map, ASSETS_DIR, &output_dir);
 }
@@ -0,0 +1,188 @@
+use integration::{
+    codec::{first_difference, BatchCodec},
+    kzg::blob_commitment,
+};
+use prover::{
+    init_env_and_log, read_json_deep, BatchHeader, BatchProvingTask, ChunkInfo, MAX_AGG_SNARKS,
+};
+use serde_derive::Deserialize;
+
+/// Batch task without blob bytes, e.g. of codec v3.
+#[derive(Deserialize)]
+struct BatchTaskWithoutBlob {
+    chunk_infos: Vec<ChunkInfo>,
+    batch_header: BatchHeader<MAX_AGG_SNARKS>,
+}
+
+fn batch_task_paths() -> Vec<String> {
+    [
+        "tests/test_data/*.json",
+        "tests/test_data/batch_tasks/*.json",
+    ]
+    .iter()
+    .flat_map(|pattern| glob::glob(pattern).unwrap())
+    .map(|path| path.unwrap().to_string_lossy().to_string())
+    .filter(|path| {
+        let task: serde_json::Value = read_json_deep(path).unwrap();
+        task.get("chunk_infos").is_some()
+    })
+    .collect()
+}
+
+/// Chunks, header and blob bytes (if any) of the batch task.
+fn load_batch_task(path: &str) -> (Vec<ChunkInfo>, BatchHeader<MAX_AGG_SNARKS>, Option<Vec<u8>>) {
+    let task: serde_json::Value = read_json_deep(path).unwrap();
+    if task.get("blob_bytes").is_some() {
+        let task: BatchProvingTask = read_json_deep(path).unwrap();
+        let chunks = task
+            .chunk_proofs
+            .iter()
+            .map(|proof| proof.inner.chunk_info().clone())
+            .collect();
+        (chunks, task.batch_header, Some(task.blob_bytes))
+    } else {
+        let task: BatchTaskWithoutBlob = read_json_deep(path).unwrap();
+        (task.chunk_infos, task.batch_header, None)
+    }
+}
+
+/// Codec of the task, where v4 blobs tell whether they are compressed. Tasks without blob bytes
+/// don't, and are of compressed blobs unless they are `no-encode` ones.
+fn task_codec(
+    path: &str,
+    header: &BatchHeader<MAX_AGG_SNARKS>,
+    blob_bytes: Option<&[u8]>,
+) -> BatchCodec {
+    let compressed = match blob_bytes {
+        Some(blob_bytes) => {
+            BatchCodec::from_version(header.version)
+                .unwrap()
+                .decode_blob(blob_bytes)
+                .unwrap()
+                .compressed
+        }
+        None => !path.contains("no-encode"),
+    };
+    match header.version {
+        1 => BatchCodec::V1,
+        2 => BatchCodec::V2 { compressed },
+        3 => BatchCodec::V3 { compressed },
+        4 => BatchCodec::V4 { compressed },
+        version => panic!("{path}: unknown batch version {version}"),
+    }
+}
+
+#[test]
+fn test_blob_round_trip() {
+    init_env_and_log("codec_tests");
+
+    let paths = batch_task_paths();
+    assert!(!paths.is_empty());
+    for path in paths {
+        let (chunks, header, blob_bytes) = load_batch_task(&path);
+        let codec = task_codec(&path, &header, blob_bytes.as_deref());
+        log::info!("{path}: {} chunks of codec {codec:?}", chunks.len());
+
+        let encoded = codec.blob_bytes(&chunks).unwrap();
+        // The blob of the task, or the one committed to by the header on L1.
+        match &blob_bytes {
+            Some(blob_bytes) => assert_eq!(first_difference(&encoded, blob_bytes), None, "{path}"),
+            None => assert_eq!(
+                blob_commitment(codec, &chunks).unwrap().versioned_hash,
+                header.blob_versioned_hash,
+                "{path}"
+            ),
+        }
+
+        let decoded = codec.decode_blob(&encoded).unwrap();
+        assert_eq!(
+            decoded.chunks,
+            chunks
+                .iter()
+                .map(|chunk| chunk.tx_bytes.clone())
+                .collect::<Vec<_>>(),
+            "{path}"
+        );
+        assert_eq!(
+            decoded.chunk_sizes,
+            chunks
+                .iter()
+                .map(|chunk| chunk.tx_bytes.len() as u32)
+                .collect::<Vec<_>>(),
+            "{path}"
+        );
+        assert_eq!(
+            decoded.metadata,
+            codec.batch_bytes(&chunks)[..decoded.metadata.len()]
+        );
+        // Padding of the blob is ignored.
+        let padded = [encoded, vec![0; 64]].concat();
+        assert_eq!(
+            codec.decode_blob(&padded).unwrap().mismatch(&decoded),
+            None,
+            "{path}"
+        );
+    }
+}
+
+#[test]
+fn test_blob_commitment() {
+    init_env_and_log("codec_tests");
+
+    for path in batch_task_paths() {
+        let (chunks, header, blob_bytes) = load_batch_task(&path);
+        let codec = task_codec(&path, &header, blob_bytes.as_deref());
+        let commitment = blob_commitment(codec, &chunks).unwrap();
+        log::info!("{path}: {commitment:?}");
+        // The headers of the tasks are those on L1.
+        assert_eq!(
+            commitment.versioned_hash, header.blob_versioned_hash,
+            "{path}"
+        );
+        assert_eq!(
+            [commitment.challenge, commitment.evaluation],
+            header.blob_data_proof,
+            "{path}"
+        );
+        assert_eq!(
+            (commitment.commitment.len(), commitment.proof.len()),
+            (48, 48)
+        );
+    }
+}
+
+#[test]
+fn test_blob_commitment_v1() {
+    init_env_and_log("codec_tests");
+
+    let (chunks, ..) = load_batch_task("tests/test_data/batch-task-with-blob-raw.json");
+    assert!(blob_commitment(BatchCodec::V1, &chunks[..1]).is_err());
+}
+
+#[test]
+fn test_blob_mismatch() {
+    init_env_and_log("codec_tests");
+
+    let (chunks, ..) = load_batch_task("tests/test_data/batch-task-with-blob-raw.json");
+    let codec = BatchCodec::V4 { compressed: false };
+    let decoded = codec
+        .decode_blob(&codec.blob_bytes(&chunks).unwrap())
+        .unwrap();
+
+    let mut chunks = chunks;
+    chunks[1].tx_bytes[10] ^= 1;
+    let other = codec
+        .decode_blob(&codec.blob_bytes(&chunks).unwrap())
+        .unwrap();
+    assert_eq!(
+        decoded.mismatch(&other).as_deref(),
+        Some(
+            format!(
+                "chunk 1 of len {len} vs {len} differs at byte 10",
+                len = chunks[1].tx_bytes.len()
+            )
+            .as_str()
+        )
+    );
+    assert!(codec.decode_blob(&[2]).is_err());
+}
@@ -1,7 +1,8 @@
 use halo2_proofs::{halo2curves::bn256::Bn256, poly::kzg::commitment::ParamsKZG};
 use integration::{
+    codec::BatchCodec,
     prove::get_blob_from_chunks,
-    test_util::{load_batch, load_chunk, ASSETS_DIR, PARAMS_DIR},
+    test_util::{assert_l1_queue, assert_traces, load_batch, load_chunk, ASSETS_DIR, PARAMS_DIR},
 };
 use prover::{
     eth_types::H256, init_env_and_log, BatchHeader, BatchProvingTask, ChunkProver,
@@ -18,7 +19,7 @@ fn test_batch_pi_consistency() {
 
     let batch_dir = prover::read_env_var("TRACE_PATH", "./tests/extra_traces/batch_25".to_string());
     let trace_paths = load_batch(&batch_dir).unwrap();
-    log_batch_pi(&trace_paths);
+    log_batch_pi(&trace_paths, BatchCodec::default());
 }
 */
 
@@ -171,6 +172,12 @@ fn gen_batch_proving_task(
         .iter()
         .map(|chunk_dir| load_chunk(chunk_dir).1)
         .collect();
+    // Each chunk continues the L1 message queue of the previous one.
+    let mut next_l1_queue_index = None;
+    for traces in &chunks {
+        assert_traces(traces);
+        next_l1_queue_index = Some(assert_l1_queue(traces, next_l1_queue_index));
+    }
     let l1_message_popped = chunks
         .iter()
         .flatten()
@@ -212,10 +219,13 @@ fn gen_batch_proving_task(
         .map(|cp| cp.inner.chunk_info().clone())
         .collect::<Vec<_>>();
 
-    let blob_bytes = get_blob_from_chunks(&chunks);
+    // Batches follow the codec of the parent batch.
+    let codec =
+        BatchCodec::from_version(opt_batch_header.map_or(4, |header| header.version)).unwrap();
+    let blob_bytes = get_blob_from_chunks(codec, &chunks).unwrap();
 
     let batch_header = BatchHeader::construct_from_chunks(
-        opt_batch_header.map_or(4, |header| header.version),
+        codec.version(),
         opt_batch_header.map_or(123, |header| header.batch_index + 1),
         l1_message_popped,
         opt_batch_header.map_or(l1_message_popped, |header| {
@@ -239,7 +249,8 @@ fn gen_batch_proving_task(
 
 /*
 #[cfg(feature = "fix_later")]
-fn log_batch_pi(trace_paths: &[String]) {
+/// `codec` is that of the batch being proven, as `gen_batch_proving_task` picks it.
+fn log_batch_pi(trace_paths: &[String], codec: BatchCodec) {
     let max_num_snarks = prover::MAX_AGG_SNARKS;
     let chunk_traces: Vec<_> = trace_paths
         .iter()
@@ -266,7 +277,7 @@ fn log_batch_pi(trace_paths: &[String]) {
             ChunkInfo::from_witness_block(&witness_block, false)
         })
         .collect();
-    let blob_bytes = get_blob_from_chunks(&chunk_hashes);
+    let blob_bytes = get_blob_from_chunks(codec, &chunk_hashes).unwrap();
     let real_chunk_count = chunk_hashes.len();
     if real_chunk_count < max_num_snarks {
         let mut padding_chunk_hash = chunk_hashes.last().unwrap().clone();
@@ -283,7 +294,7 @@ fn log_batch_pi(trace_paths: &[String]) {
         0, 0, 0, 0, 0, 0, 0,
     ]);
     let batch_header = BatchHeader {
-        version: 3,
+        version: codec.version(),
         batch_index: 123,
         l1_message_popped,
         total_l1_message_popped: l1_message_popped,
@@ -0,0 +1,125 @@
+use integration::{
+    l1_queue::{check_l1_queue, QueueViolation},
+    test_util::load_chunk,
+};
+use prover::{eth_types::l2_types::BlockTrace, init_env_and_log};
+
+fn load_batch_24() -> Vec<BlockTrace> {
+    ["chunk_115", "chunk_116"]
+        .iter()
+        .flat_map(|chunk| load_chunk(&format!("./tests/extra_traces/batch_24/{chunk}")).1)
+        .collect()
+}
+
+/// Index of the first block with an L1 message and the index of the message in the block.
+fn first_l1_tx(traces: &[BlockTrace]) -> (usize, usize) {
+    traces
+        .iter()
+        .enumerate()
+        .find_map(|(block_idx, trace)| {
+            trace
+                .transactions
+                .iter()
+                .position(|tx| tx.is_l1_tx())
+                .map(|tx_idx| (block_idx, tx_idx))
+        })
+        .unwrap()
+}
+
+#[test]
+fn test_l1_queue_contiguous() {
+    init_env_and_log("l1_queue_tests");
+
+    let traces = load_batch_24();
+    let last = traces.last().unwrap();
+    let (next, skipped) = check_l1_queue(&traces, Some(traces[0].start_l1_queue_index)).unwrap();
+    assert_eq!(next, last.start_l1_queue_index + last.num_l1_txs());
+    assert!(skipped.is_empty());
+
+    // Chunks chain by the next queue index.
+    let (chunk0, chunk1) = traces.split_at(25);
+    let (next0, _) = check_l1_queue(chunk0, None).unwrap();
+    assert_eq!(check_l1_queue(chunk1, Some(next0)), Ok((next, vec![])));
+    assert!(check_l1_queue(chunk1, Some(next0 + 1)).is_err());
+}
+
+#[test]
+fn test_l1_queue_violations() {
+    init_env_and_log("l1_queue_tests");
+
+    let traces = load_batch_24();
+    let (block_idx, tx_idx) = first_l1_tx(&traces);
+    let block_num = traces[block_idx].header.number.unwrap().as_u64();
+    let queue_index = traces[block_idx].transactions[tx_idx].nonce;
+
+    // The message skips one, which doesn't break the queue.
+    let mut skipped = traces.clone();
+    for (idx, trace) in skipped.iter_mut().enumerate().skip(block_idx) {
+        if idx > block_idx {
+            trace.start_l1_queue_index += 1;
+        }
+        let from = if idx == block_idx { tx_idx } else { 0 };
+        for tx in trace.transactions.iter_mut().skip(from) {
+            if tx.is_l1_tx() {
+                tx.nonce += 1;
+            }
+        }
+    }
+    let last = traces.last().unwrap();
+    assert_eq!(
+        check_l1_queue(&skipped, None),
+        Ok((
+            last.start_l1_queue_index + last.num_l1_txs() + 1,
+            vec![QueueViolation::Skipped {
+                block_num,
+                tx_index: tx_idx,
+                from: queue_index,
+                to: queue_index + 1,
+            }]
+        ))
+    );
+
+    // The message is popped twice.
+    let mut overlapped = traces.clone();
+    let tx = overlapped[block_idx].transactions[tx_idx].clone();
+    overlapped[block_idx].transactions.insert(tx_idx + 1, tx);
+    assert_eq!(
+        check_l1_queue(&overlapped, None).unwrap_err(),
+        vec![QueueViolation::Overlap {
+            block_num,
+            tx_index: tx_idx + 1,
+            queue_index,
+            expected: queue_index + 1,
+        }]
+    );
+
+    // The next block starts after a gap.
+    let expected = traces[block_idx].start_l1_queue_index + traces[block_idx].num_l1_txs();
+    let mut gapped = traces;
+    gapped[block_idx + 1].start_l1_queue_index += 1;
+    assert_eq!(
+        check_l1_queue(&gapped, None).unwrap_err(),
+        vec![QueueViolation::Gap {
+            block_num: block_num + 1,
+            expected,
+            start_l1_queue_index: expected + 1,
+        }]
+    );
+}
+
+#[test]
+fn test_l1_queue_block_gap() {
+    init_env_and_log("l1_queue_tests");
+
+    let mut traces = load_batch_24();
+    let block_num = traces[10].header.number.unwrap().as_u64();
+    traces.remove(10);
+    let violations = check_l1_queue(&traces, None).unwrap_err();
+    assert_eq!(
+        violations[0],
+        QueueViolation::BlockGap {
+            block_num: block_num + 1,
+            prev_block_num: block_num - 1,
+        }
+    );
+}
@@ -0,0 +1,79 @@
+use integration::{
+    l2geth::split_batch_response,
+    test_util::load_chunk,
+    trace_options::{Hardfork, StorageProofFormat, TraceOptions},
+};
+use prover::{eth_types::l2_types::BlockTrace, init_env_and_log};
+use serde_json::json;
+
+#[test]
+fn test_split_batch_response() {
+    init_env_and_log("l2geth_tests");
+
+    let (_, traces) = load_chunk("./tests/extra_traces/batch_25/chunk_112");
+    let traces = &traces[..3];
+    // Out of order, with a failed request, a missing response and an unknown id.
+    let responses = vec![
+        json!({"jsonrpc": "2.0", "id": 2, "result": traces[2]}),
+        json!({"jsonrpc": "2.0", "id": 0, "result": traces[0]}),
+        json!({"jsonrpc": "2.0", "id": 1, "error": {"code": -32000, "message": "timeout"}}),
+        json!({"jsonrpc": "2.0", "id": 9, "result": traces[1]}),
+    ];
+    let results = split_batch_response::<BlockTrace>(4, responses);
+    assert_eq!(results.len(), 4);
+    for i in [0, 2] {
+        let trace = results[i].as_ref().unwrap();
+        assert_eq!(trace.header.hash, traces[i].header.hash);
+    }
+    let error = results[1].as_ref().unwrap_err().to_string();
+    assert!(error.contains("timeout"), "{error}");
+    assert!(results[3].is_err());
+
+    // A null result, e.g. of a block not produced yet, is an error of its request only.
+    let responses = vec![
+        json!({"jsonrpc": "2.0", "id": 0, "result": null}),
+        json!({"jsonrpc": "2.0", "id": 1, "result": traces[1]}),
+    ];
+    let results = split_batch_response::<BlockTrace>(2, responses);
+    assert!(results[0].is_err());
+    assert!(results[1].is_ok());
+}
+
+#[test]
+fn test_trace_options() {
+    let options = TraceOptions::default();
+    assert_eq!(options.to_param(), json!({"StorageProofFormat": "legacy"}));
+    assert_eq!(options.cache_key(), "legacy");
+
+    // The former curie override.
+    let curie = TraceOptions::default().with_hardfork(Hardfork::Curie);
+    assert_eq!(curie.to_param(), json!({"overrides": {"curieBlock": 1}}));
+    assert_eq!(curie.cache_key(), "legacy_curie-1");
+
+    let overrides = TraceOptions::parse_overrides("curie=1, darwin=1700000000").unwrap();
+    let darwin = TraceOptions {
+        storage_proof_format: StorageProofFormat::Flatten,
+        overrides,
+        ..Default::default()
+    }
+    .with_hardfork(Hardfork::DarwinV2);
+    assert_eq!(darwin.to_param()["StorageProofFormat"], json!("flatten"));
+    assert_eq!(
+        darwin.to_param()["overrides"],
+        json!({"curieBlock": 1, "darwinTime": 1700000000u64, "darwinV2Time": 0})
+    );
+    assert_eq!(
+        darwin.cache_key(),
+        "flatten_curie-1_darwin-1700000000_darwin_v2-0"
+    );
+    assert!(TraceOptions::parse_overrides("shanghai=1").is_err());
+
+    // Extra tracer options get a cache key of their own.
+    let mut extra = TraceOptions::default();
+    extra
+        .extra
+        .insert("disableStorage".to_string(), true.into());
+    assert_eq!(extra.to_param()["disableStorage"], json!(true));
+    assert_ne!(extra.cache_key(), options.cache_key());
+    assert!(extra.cache_key().starts_with("legacy_x"));
+}
@@ -0,0 +1,320 @@
+use integration::{
+    capacity_checker::{prepare_circuit_capacity_checker, CCCMode},
+    codec::BatchCodec,
+    proposer::{
+        chunk_info_from_traces, first_batch_cut, first_chunk_cut, BatchBuilder, BatchCut,
+        BatchLimits, ChunkBuilder, ChunkCut, ChunkLimits,
+    },
+    test_util::load_chunk,
+};
+use prover::{
+    chunk_trace_to_witness_block, eth_types::l2_types::BlockTrace, init_env_and_log, ChunkInfo,
+    MAX_AGG_SNARKS,
+};
+
+fn load_traces(chunk_dirs: &[&str]) -> Vec<BlockTrace> {
+    chunk_dirs
+        .iter()
+        .flat_map(|dir| load_chunk(&format!("./tests/extra_traces/{dir}")).1)
+        .collect()
+}
+
+/// Sizes and cuts of the chunks built from `traces`, and the number of blocks left pending.
+fn build_chunks(
+    limits: ChunkLimits,
+    traces: Vec<BlockTrace>,
+) -> (Vec<usize>, Vec<ChunkCut>, usize) {
+    let mut chunk_builder = ChunkBuilder::new(limits);
+    let (sizes, cuts) = traces
+        .into_iter()
+        .filter_map(|trace| chunk_builder.add(trace))
+        .map(|chunk| (chunk.traces.len(), chunk.cut))
+        .unzip();
+    let pending = chunk_builder.finish().map_or(0, |chunk| chunk.traces.len());
+    (sizes, cuts, pending)
+}
+
+fn chunk_info(chunk_dir: &str) -> ChunkInfo {
+    let witness_block = chunk_trace_to_witness_block(load_traces(&[chunk_dir])).unwrap();
+    ChunkInfo::from_witness_block(&witness_block, false)
+}
+
+fn limits_without_ccc() -> ChunkLimits {
+    ChunkLimits {
+        ccc_mode: None,
+        ..Default::default()
+    }
+}
+
+const BATCH_25: [&str; 2] = ["batch_25/chunk_112", "batch_25/chunk_113"];
+
+#[test]
+fn test_chunk_builder_max_blocks() {
+    init_env_and_log("proposer_tests");
+
+    let limits = ChunkLimits {
+        max_blocks: Some(8),
+        ..limits_without_ccc()
+    };
+    assert_eq!(
+        build_chunks(limits, load_traces(&BATCH_25)),
+        (vec![8, 8, 8], vec![ChunkCut::MaxBlocks; 3], 6)
+    );
+}
+
+#[test]
+fn test_chunk_builder_max_gas() {
+    init_env_and_log("proposer_tests");
+
+    let limits = ChunkLimits {
+        max_gas: Some(1_000_000),
+        ..limits_without_ccc()
+    };
+    assert_eq!(
+        build_chunks(limits, load_traces(&BATCH_25)),
+        (vec![26, 1, 2], vec![ChunkCut::MaxGas; 3], 1)
+    );
+}
+
+#[test]
+fn test_chunk_builder_max_tx_payload_bytes() {
+    init_env_and_log("proposer_tests");
+
+    let limits = ChunkLimits {
+        max_tx_payload_bytes: Some(2000),
+        ..limits_without_ccc()
+    };
+    assert_eq!(
+        build_chunks(limits, load_traces(&BATCH_25)),
+        (vec![26, 1, 2], vec![ChunkCut::MaxTxPayloadBytes; 3], 1)
+    );
+}
+
+#[test]
+fn test_chunk_builder_combined_limits() {
+    init_env_and_log("proposer_tests");
+
+    let limits = ChunkLimits {
+        max_blocks: Some(8),
+        max_gas: Some(1_000_000),
+        ..limits_without_ccc()
+    };
+    let (sizes, cuts, pending) = build_chunks(limits, load_traces(&BATCH_25));
+    assert_eq!((sizes, pending), (vec![8, 8, 8, 2, 1, 2], 1));
+    assert_eq!(cuts[..3], [ChunkCut::MaxBlocks; 3]);
+    assert_eq!(cuts[3..], [ChunkCut::MaxGas; 3]);
+}
+
+#[test]
+fn test_chunk_builder_max_l1_messages() {
+    init_env_and_log("proposer_tests");
+
+    // The 5th block has 2 L1 messages, which exceeds the limit by itself.
+    for max_l1_messages in [0, 1] {
+        let limits = ChunkLimits {
+            max_l1_messages: Some(max_l1_messages),
+            ..limits_without_ccc()
+        };
+        assert_eq!(
+            build_chunks(limits, load_traces(&["batch_24/chunk_115"])),
+            (vec![4, 1], vec![ChunkCut::MaxL1Messages; 2], 20)
+        );
+    }
+}
+
+#[test]
+fn test_chunk_builder_restore() {
+    init_env_and_log("proposer_tests");
+
+    let limits = ChunkLimits {
+        max_blocks: Some(8),
+        ..limits_without_ccc()
+    };
+    let mut traces = load_traces(&BATCH_25);
+    let mut chunk_builder = ChunkBuilder::new(limits);
+    chunk_builder.restore(traces.drain(..5).collect(), Default::default());
+    assert_eq!(chunk_builder.pending().len(), 5);
+
+    let chunk = traces.drain(..3).find_map(|trace| chunk_builder.add(trace));
+    assert_eq!(chunk.map(|chunk| chunk.traces.len()), Some(8));
+    assert!(chunk_builder.pending().is_empty());
+}
+
+#[test]
+fn test_chunk_builder_ccc_modes() {
+    init_env_and_log("proposer_tests");
+    prepare_circuit_capacity_checker();
+
+    // The blocks are far below the circuit capacity, so the modes only differ in row usage.
+    for ccc_mode in [
+        CCCMode::Optimal,
+        CCCMode::Siger,
+        CCCMode::FollowerFull,
+        CCCMode::Sequencer,
+    ] {
+        let limits = ChunkLimits {
+            max_blocks: Some(4),
+            ccc_mode: Some(ccc_mode),
+            ..Default::default()
+        };
+        let mut chunk_builder = ChunkBuilder::new(limits);
+        let chunks: Vec<_> = load_traces(&["batch_73224/chunk_562585"])
+            .into_iter()
+            .filter_map(|trace| chunk_builder.add(trace))
+            .collect();
+        assert_eq!(chunks.len(), 1, "{ccc_mode:?}");
+        assert_eq!(chunks[0].cut, ChunkCut::MaxBlocks, "{ccc_mode:?}");
+        assert!(chunks[0].row_usage.is_ok, "{ccc_mode:?}");
+        log::info!("{ccc_mode:?}: {:?}", chunks[0].row_usage);
+    }
+}
+
+#[test]
+fn test_chunk_builder_max_rows() {
+    init_env_and_log("proposer_tests");
+    prepare_circuit_capacity_checker();
+
+    let limits = ChunkLimits {
+        max_rows: Some(1),
+        ccc_mode: Some(CCCMode::Sequencer),
+        ..Default::default()
+    };
+    let (sizes, cuts, pending) = build_chunks(limits, load_traces(&["batch_73224/chunk_562585"]));
+    assert_eq!((sizes, pending), (vec![1, 1, 1], 1));
+    assert!(cuts
+        .iter()
+        .all(|cut| matches!(cut, ChunkCut::CircuitCapacity(_))));
+}
+
+#[test]
+fn test_batch_builder_max_chunks() {
+    init_env_and_log("proposer_tests");
+
+    let mut batch_builder = BatchBuilder::new(BatchLimits {
+        max_chunks: 2,
+        ..Default::default()
+    });
+    let chunks = [
+        "batch_300/chunk_12263",
+        "batch_73224/chunk_562585",
+        "batch_25/chunk_112",
+    ]
+    .map(chunk_info);
+    let [chunk0, chunk1, chunk2] = chunks;
+
+    assert!(batch_builder.add(chunk0).is_none());
+    let batch = batch_builder.add(chunk1).unwrap();
+    assert_eq!((batch.chunks.len(), batch.cut), (2, BatchCut::MaxChunks));
+    assert!(batch_builder.add(chunk2).is_none());
+    let batch = batch_builder.finish().unwrap();
+    assert_eq!((batch.chunks.len(), batch.cut), (1, BatchCut::Finish));
+    assert!(batch_builder.finish().is_none());
+}
+
+#[test]
+fn test_batch_builder_max_blob_bytes() {
+    init_env_and_log("proposer_tests");
+
+    let mut batch_builder = BatchBuilder::new(BatchLimits {
+        max_blob_bytes: 1,
+        ..Default::default()
+    });
+    for chunk_dir in ["batch_300/chunk_12263", "batch_73224/chunk_562585"] {
+        let batch = batch_builder.add(chunk_info(chunk_dir)).unwrap();
+        assert_eq!((batch.chunks.len(), batch.cut), (1, BatchCut::BlobOverflow));
+    }
+    assert!(batch_builder.finish().is_none());
+}
+
+#[test]
+fn test_batch_codec_blob_bytes() {
+    init_env_and_log("proposer_tests");
+
+    let chunks = ["batch_300/chunk_12263", "batch_73224/chunk_562585"].map(chunk_info);
+    let v1 = BatchCodec::V1;
+    assert_eq!(v1.blob_bytes(&chunks).unwrap(), v1.batch_bytes(&chunks));
+    let v3_raw: BatchCodec = "v3-raw".parse().unwrap();
+    assert_eq!(
+        v3_raw.blob_bytes(&chunks).unwrap(),
+        v3_raw.batch_bytes(&chunks)
+    );
+    let v4_raw: BatchCodec = "v4-raw".parse().unwrap();
+    let blob_bytes = v4_raw.blob_bytes(&chunks).unwrap();
+    assert_eq!(blob_bytes[0], 0);
+    assert_eq!(blob_bytes[1..], v4_raw.batch_bytes(&chunks));
+    // v1 has fewer chunk slots, so less room for the chunks in the same blob.
+    assert!(v1.max_chunk_bytes() < v4_raw.max_chunk_bytes());
+
+    // The builder sizes the blob by its codec, so the raw blob of both chunks reaches the limit.
+    let mut batch_builder = BatchBuilder::new(BatchLimits {
+        max_blob_bytes: blob_bytes.len(),
+        codec: v4_raw,
+        ..Default::default()
+    });
+    let [chunk0, chunk1] = chunks;
+    assert!(batch_builder.add(chunk0).is_none());
+    let batch = batch_builder.add(chunk1).unwrap();
+    assert_eq!((batch.chunks.len(), batch.cut), (1, BatchCut::BlobOverflow));
+}
+
+#[test]
+#[should_panic]
+fn test_batch_builder_codec_max_chunks() {
+    BatchBuilder::new(BatchLimits {
+        max_chunks: MAX_AGG_SNARKS,
+        codec: BatchCodec::V1,
+        ..Default::default()
+    });
+}
+
+#[test]
+fn test_first_chunk_cut() {
+    init_env_and_log("proposer_tests");
+
+    let limits = ChunkLimits {
+        max_gas: Some(1_000_000),
+        ..limits_without_ccc()
+    };
+    let traces = load_traces(&BATCH_25);
+    // The chunk of the first 26 blocks is cut when the 27th block comes.
+    assert_eq!(
+        first_chunk_cut(&limits, &traces[..26], Some(&traces[26])),
+        Some((26, ChunkCut::MaxGas))
+    );
+    assert_eq!(first_chunk_cut(&limits, &traces[..26], None), None);
+    assert_eq!(
+        first_chunk_cut(&limits, &traces[..27], None),
+        Some((26, ChunkCut::MaxGas))
+    );
+}
+
+#[test]
+fn test_first_batch_cut() {
+    init_env_and_log("proposer_tests");
+
+    let limits = BatchLimits {
+        max_chunks: 2,
+        ..Default::default()
+    };
+    let chunks = ["batch_300/chunk_12263", "batch_73224/chunk_562585"].map(chunk_info);
+    assert_eq!(
+        first_batch_cut(&limits, &chunks[..1], Some(&chunks[1])),
+        Some((2, BatchCut::MaxChunks))
+    );
+    assert_eq!(first_batch_cut(&limits, &chunks[..1], None), None);
+}
+
+#[test]
+fn test_chunk_info_from_traces() {
+    init_env_and_log("proposer_tests");
+
+    for chunk_dir in [
+        "batch_300/chunk_12263",
+        "batch_73224/chunk_562585",
+        "batch_25/chunk_112",
+        "batch_24/chunk_115",
+    ] {
+        let chunk_info = chunk_info_from_traces(&load_traces(&[chunk_dir]));
+        assert_eq!(chunk_info, self::chunk_info(chunk_dir), "{chunk_dir}");
+    }
+}
@@ -0,0 +1,104 @@
+use integration::{
+    root_check::{
+        check_block_roots, check_chunk_proof_roots, check_roots, RootMismatch, RootViolation, Roots,
+    },
+    test_util::load_chunk,
+};
+use prover::{eth_types::l2_types::BlockTrace, init_env_and_log, read_json_deep, BatchProvingTask};
+
+fn load_batch_24() -> Vec<Vec<BlockTrace>> {
+    ["chunk_115", "chunk_116"]
+        .iter()
+        .map(|chunk| load_chunk(&format!("./tests/extra_traces/batch_24/{chunk}")).1)
+        .collect()
+}
+
+#[test]
+fn test_block_roots() {
+    init_env_and_log("root_check_tests");
+
+    let chunks = load_batch_24();
+    assert_eq!(check_block_roots(chunks.iter().flatten()), vec![]);
+
+    let mut traces = chunks.concat();
+    let block_num = traces[30].header.number.unwrap().as_u64();
+    traces[30].storage_trace.root_before = Default::default();
+    let violations = check_block_roots(&traces);
+    assert_eq!(violations.len(), 1);
+    assert_eq!(
+        (violations[0].index, violations[0].block_num),
+        (30, Some(block_num))
+    );
+    assert!(matches!(
+        violations[0].mismatch,
+        RootMismatch::StateRoot { .. }
+    ));
+
+    // Blocks not following each other are a violation by themselves.
+    traces.remove(29);
+    assert_eq!(
+        check_block_roots(&traces),
+        vec![RootViolation {
+            index: 29,
+            block_num: Some(block_num),
+            mismatch: RootMismatch::BlockRange {
+                last: block_num - 2,
+                next_first: block_num,
+            },
+        }]
+    );
+}
+
+#[test]
+fn test_chunk_roots() {
+    init_env_and_log("root_check_tests");
+
+    let chunks = load_batch_24()
+        .iter()
+        .map(|traces| Roots::from_chunk_traces(traces))
+        .collect::<Vec<_>>();
+    assert_eq!(check_roots(&chunks), vec![]);
+
+    let mut swapped = chunks.clone();
+    swapped.swap(0, 1);
+    let violations = check_roots(&swapped);
+    let (last, next_first) = (swapped[0].blocks.unwrap().1, swapped[1].blocks.unwrap().0);
+    assert_eq!(
+        violations,
+        vec![RootViolation {
+            index: 1,
+            block_num: Some(next_first),
+            mismatch: RootMismatch::BlockRange { last, next_first },
+        }]
+    );
+    let swapped = swapped
+        .into_iter()
+        .map(|chunk| Roots {
+            blocks: None,
+            ..chunk
+        })
+        .collect::<Vec<_>>();
+    let violations = check_roots(&swapped);
+    assert_eq!(violations.len(), 1);
+    assert_eq!((violations[0].index, violations[0].block_num), (1, None));
+
+    let mut other_chain = chunks;
+    other_chain[1].chain_id += 1;
+    assert!(matches!(
+        check_roots(&other_chain)[0].mismatch,
+        RootMismatch::ChainId { .. }
+    ));
+}
+
+#[test]
+fn test_chunk_proof_roots() {
+    init_env_and_log("root_check_tests");
+
+    let batch: BatchProvingTask =
+        read_json_deep("tests/test_data/batch-task-with-blob.json").unwrap();
+    assert_eq!(check_chunk_proof_roots(&batch.chunk_proofs), vec![]);
+
+    let mut chunk_proofs = batch.chunk_proofs;
+    chunk_proofs.remove(1);
+    assert_eq!(check_chunk_proof_roots(&chunk_proofs)[0].index, 1);
+}
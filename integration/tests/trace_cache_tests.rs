@@ -0,0 +1,81 @@
+use integration::{
+    capacity_checker::split_block_trace_by_tx,
+    test_util::load_chunk,
+    trace_cache::{CacheMode, TraceCache, TraceKind},
+};
+use prover::{eth_types::l2_types::BlockTrace, init_env_and_log};
+use std::fs;
+
+fn new_cache(name: &str) -> TraceCache {
+    let dir = std::env::temp_dir().join(format!("trace_cache_tests_{name}_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    TraceCache::new(dir, CacheMode::ReadThrough)
+}
+
+#[test]
+fn test_trace_cache_layout() {
+    init_env_and_log("trace_cache_tests");
+
+    let cache = new_cache("layout");
+    let (_, traces) = load_chunk("./tests/extra_traces/batch_25/chunk_112");
+    let chain_id = traces[0].chain_id;
+    for trace in &traces {
+        let block_num = trace.header.number.unwrap().as_u64();
+        let path = cache.path(chain_id, "legacy", TraceKind::Block, block_num);
+        assert!(cache
+            .read::<BlockTrace>(&path, block_num)
+            .unwrap()
+            .is_none());
+        cache.write(&path, trace).unwrap();
+
+        let txbytx = split_block_trace_by_tx(trace);
+        let path = cache.path(chain_id, "legacy", TraceKind::TxByTx, block_num);
+        cache.write(&path, &txbytx).unwrap();
+        let cached: Vec<BlockTrace> = cache.read(&path, block_num).unwrap().unwrap();
+        assert_eq!(cached.len(), txbytx.len());
+    }
+
+    // The cache dir of the options is a chunk dir.
+    let dir = cache.block_dir(chain_id, "legacy");
+    let (_, cached) = load_chunk(dir.to_str().unwrap());
+    assert_eq!(
+        cached.iter().map(|t| t.header.hash).collect::<Vec<_>>(),
+        traces.iter().map(|t| t.header.hash).collect::<Vec<_>>()
+    );
+    assert_eq!(cache.check_integrity().unwrap(), vec![]);
+}
+
+#[test]
+fn test_trace_cache_integrity() {
+    init_env_and_log("trace_cache_tests");
+
+    let cache = new_cache("integrity");
+    let (_, traces) = load_chunk("./tests/extra_traces/batch_300/chunk_12263");
+    let chain_id = traces[0].chain_id;
+    let block_num = traces[0].header.number.unwrap().as_u64();
+    let path = cache.path(chain_id, "legacy", TraceKind::Block, block_num);
+    cache.write(&path, &traces[0]).unwrap();
+
+    // Truncated by an interrupted write.
+    let bytes = fs::read(&path).unwrap();
+    fs::write(&path, &bytes[..bytes.len() / 2]).unwrap();
+    assert!(cache.read::<BlockTrace>(&path, block_num).is_err());
+    let broken = cache.check_integrity().unwrap();
+    assert_eq!(broken.len(), 1);
+    assert_eq!(broken[0].0, path);
+
+    // Trace of another block.
+    let other_path = cache.path(chain_id, "legacy", TraceKind::Block, block_num + 100);
+    cache.write(&other_path, &traces[0]).unwrap();
+    assert!(cache
+        .read::<BlockTrace>(&other_path, block_num + 100)
+        .is_err());
+    assert_eq!(cache.check_integrity().unwrap().len(), 2);
+
+    // Rewriting repairs the file.
+    cache.write(&path, &traces[0]).unwrap();
+    assert!(cache
+        .read::<BlockTrace>(&path, block_num)
+        .unwrap()
+        .is_some());
+}
@@ -0,0 +1,136 @@
+use integration::{
+    test_util::load_chunk,
+    trace_check::{check_block_trace, check_traces, TraceViolation},
+};
+use prover::{
+    eth_types::{l2_types::BlockTrace, H256},
+    init_env_and_log,
+};
+use serde_json::{json, Value};
+
+const CHUNK_DIR: &str = "./tests/extra_traces/batch_24/chunk_115";
+
+fn block_num(trace: &BlockTrace) -> u64 {
+    trace.header.number.unwrap().as_u64()
+}
+
+#[test]
+fn test_trace_check() {
+    init_env_and_log("trace_check_tests");
+
+    let (_, traces) = load_chunk(CHUNK_DIR);
+    let chain_id = traces[0].chain_id;
+    assert_eq!(check_traces(&traces, Some(chain_id)), vec![]);
+    for trace in &traces {
+        assert_eq!(check_block_trace(trace, block_num(trace), None), vec![]);
+    }
+
+    // Trace of another block than requested.
+    let violations = check_block_trace(&traces[0], block_num(&traces[0]) + 1, None);
+    assert!(matches!(
+        violations[..],
+        [TraceViolation::BlockNumber { number: Some(n), .. }] if n == block_num(&traces[0])
+    ));
+
+    // Trace of another chain.
+    let violations = check_traces(&traces[..2], Some(chain_id + 1));
+    assert_eq!(violations.len(), 2);
+    assert!(violations.iter().all(
+        |v| matches!(v, TraceViolation::ChainId { expected, .. } if *expected == chain_id + 1)
+    ));
+
+    // Blocks link by parent hash.
+    let mut broken = traces.clone();
+    broken[3].header.parent_hash = H256::zero();
+    let violations = check_traces(&broken, None);
+    assert_eq!(
+        violations,
+        vec![TraceViolation::ParentHash {
+            block_num: block_num(&broken[3]),
+            parent_hash: H256::zero(),
+            prev_hash: broken[2].header.hash.unwrap(),
+        }]
+    );
+    // A missing block.
+    broken.remove(2);
+    assert_eq!(
+        check_traces(&broken, None),
+        vec![TraceViolation::BlockGap {
+            block_num: block_num(&broken[2]),
+            prev_block_num: block_num(&broken[1]),
+        }]
+    );
+
+    // Per-tx traces missing.
+    let mut trace = traces
+        .iter()
+        .find(|trace| trace.transactions.len() > 1)
+        .unwrap()
+        .clone();
+    let num_txs = trace.transactions.len();
+    trace.execution_results.pop();
+    trace.tx_storage_trace.clear();
+    let violations = check_traces([&trace], None);
+    assert_eq!(
+        violations,
+        vec![
+            TraceViolation::TxTraces {
+                block_num: block_num(&trace),
+                field: "tx_storage_trace",
+                len: 0,
+                num_txs,
+            },
+            TraceViolation::TxTraces {
+                block_num: block_num(&trace),
+                field: "execution_results",
+                len: num_txs - 1,
+                num_txs,
+            },
+        ]
+    );
+}
+
+// Trace of the block with `codes`, made of the bytecodes inlined in its execution results.
+fn trace_with_codes(codes: impl Fn(&Value) -> Value) -> BlockTrace {
+    let path = format!("{CHUNK_DIR}/block_2644.json");
+    let mut trace: Value = serde_json::from_slice(&std::fs::read(path).unwrap()).unwrap();
+    if let Some(result) = trace.get_mut("result") {
+        trace = result.take();
+    }
+    let results = trace["executionResults"].as_array().unwrap();
+    trace["codes"] = Value::Array(results.iter().map(codes).collect());
+    serde_json::from_value(trace).unwrap()
+}
+
+#[test]
+fn test_trace_check_codes() {
+    init_env_and_log("trace_check_tests");
+
+    let trace = trace_with_codes(|result| {
+        json!({
+            "hash": result["poseidonCodeHash"],
+            "keccakCodeHash": result["to"]["keccakCodeHash"],
+            "codeSize": result["to"]["codeSize"],
+            "code": result["byteCode"],
+        })
+    });
+    assert_eq!(check_traces([&trace], None), vec![]);
+
+    // Codes other than the called ones.
+    let trace = trace_with_codes(|result| {
+        json!({
+            "hash": result["poseidonCodeHash"],
+            "keccakCodeHash": result["to"]["keccakCodeHash"],
+            "codeSize": 1,
+            "code": "0x00",
+        })
+    });
+    let violations = check_traces([&trace], None);
+    assert_eq!(violations.len(), trace.transactions.len());
+    for (tx_index, violation) in violations.iter().enumerate() {
+        assert!(matches!(
+            violation,
+            TraceViolation::MissingCode { tx_index: i, .. } if *i == tx_index
+        ));
+    }
+}